@@ -0,0 +1,64 @@
+-- Database objects beyond the baseline schema used by the API.
+--
+-- The API probes each of them at startup (see `src/db/features.rs`). Endpoints relying on a
+-- missing object answer `501 Not Implemented`, the rest of the API is unaffected.
+
+-- items_latest: most recent MRT file of each collector and data type.
+-- Used by `/broker/latest`, `/collectors` and unknown collector lookups in broker filters.
+CREATE OR REPLACE VIEW items_latest AS
+SELECT DISTINCT ON (collector_id, data_type) *
+FROM items
+ORDER BY collector_id, data_type, ts_start DESC;
+
+-- pfx2as, pfx2as_latest: daily prefix-to-origin mappings of announced prefixes.
+-- Used by `/asninfo/{asn}/rpki`, `/rov/invalids` and the `announced` filter of `/roas/expired`
+-- and `/roas/new`.
+CREATE TABLE IF NOT EXISTS pfx2as (
+    date   date   NOT NULL,
+    prefix cidr   NOT NULL,
+    asn    bigint NOT NULL,
+    PRIMARY KEY (date, prefix, asn)
+);
+CREATE INDEX IF NOT EXISTS pfx2as_date_asn_idx ON pfx2as (date, asn);
+
+CREATE OR REPLACE VIEW pfx2as_latest AS
+SELECT date, prefix, asn
+FROM pfx2as
+WHERE date = (SELECT max(date) FROM pfx2as);
+
+-- query_history: the baseline ROA history function, extended with the optional parameters below.
+-- The ROA tables are not part of this repository, so only the interface is specified here.
+-- Callers only send the parameters they use, and the baseline parameters keep their meaning.
+--
+--   nics             text[]  TALs, replacing `nic` when set
+--   asns             bigint[] origin ASNs, replacing `asn` when set
+--   match            text    prefix match mode: `valid` (baseline behavior, default) for ROAs
+--                            validly covering `prefix`, `covering` for ROAs covering `prefix`
+--                            regardless of max length, `covered` for ROAs of `prefix` or more
+--                            specifics, `exact` for ROAs of `prefix` only
+--   date_from        date    ROAs valid within [date_from, date_to]
+--   date_to          date
+--   date_range_match text    `any` for ROAs valid on any day of the range, `all` for every day
+--   family           int     `4` or `6`, address family of the ROA prefix
+--   prefix_min_len   int     minimum prefix length of the ROA prefix
+--   prefix_max_len   int     maximum prefix length of the ROA prefix
+--
+-- Results must be ordered by (prefix, asn, max_len, tal) before `res_limit` and `res_offset` are
+-- applied, with or without the extended parameters, so that paging through all results neither
+-- skips nor repeats ROAs, e.g. `ORDER BY prefix, asn, max_len, tal LIMIT res_limit OFFSET
+-- res_offset`. The baseline function must be updated accordingly.
+--
+-- Used by `/roas` filters, `/roas/expired`, `/roas/new`, `/roas/stats`, `/roas/timeline` and
+-- requests with multiple ASNs or TALs.
+
+-- query_aspa_history: ASPA history, with the same paging and filter conventions as
+-- query_history. Used by `/aspas` and `/aspa/verify`.
+--
+--   parameters: res_limit int, res_offset int, customer_asn bigint (-1: any),
+--               provider_asn bigint (-1: any), nic text ('': any), date date ('': any),
+--               nics text[] (optional, replacing `nic`)
+--   returns:    customer_asn bigint, providers bigint[], tal text, date_ranges daterange[]
+--
+-- Results must be ordered by (customer_asn, tal) before `res_limit` and `res_offset` are applied,
+-- e.g. `ORDER BY customer_asn, tal LIMIT res_limit OFFSET res_offset`, so that collecting all
+-- ASPAs of a date page by page neither skips nor repeats ASPAs.
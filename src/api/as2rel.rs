@@ -0,0 +1,448 @@
+use crate::api::{lookup_asninfo, parse_asn_list, ApiError};
+use crate::db::{execute, execute_all, BgpkitDatabase};
+use axum::extract::{Path, Query};
+use axum::{Extension, Json};
+use chrono::NaiveDate;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// maximum number of relationship rows returned for a single query, longer results are truncated
+/// and flagged as such
+const MAX_RELATIONSHIP_ROWS: usize = 100_000;
+
+/// number of rows fetched per request when loading a full relationship dataset
+const AS2REL_PAGE_SIZE: usize = 10_000;
+
+/// maximum number of ASNs accepted by a bulk relationship request
+const BULK_MAX_ASNS: usize = 1000;
+
+/// number of ASNs on each side of a batched bulk relationship query, keeping request URLs short
+const BULK_BATCH_SIZE: usize = 200;
+
+/// number of batched bulk relationship queries in flight at once
+const BULK_CONCURRENCY: usize = 8;
+
+/// Raw AS relationship entry in CAIDA serial-1 format.
+///
+/// `rel` is `-1` when `asn1` is a provider of `asn2`, and `0` when the two ASes are peers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AsRelRawEntry {
+    /// date of the relationship dataset
+    pub date: String,
+    pub asn1: u32,
+    pub asn2: u32,
+    pub rel: i8,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AsRelationship {
+    /// first AS number, the provider in a `p2c` relationship
+    asn1: u32,
+
+    /// second AS number, the customer in a `p2c` relationship
+    asn2: u32,
+
+    /// relationship type, `p2c` (provider-to-customer) or `p2p` (peer-to-peer)
+    relationship: String,
+
+    /// date of the relationship dataset
+    date: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AsNeighbor {
+    /// neighbor AS number
+    asn: u32,
+
+    /// relationship of the neighbor to the queried AS: `provider`, `customer` or `peer`
+    relationship: String,
+
+    /// date of the relationship dataset
+    date: String,
+
+    /// neighbor AS name, only included with `names=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    as_name: Option<String>,
+
+    /// neighbor organization name, only included with `names=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AsRelationshipsResponse {
+    /// queried AS number
+    asn: u32,
+
+    /// date of the relationship dataset, empty if no relationship found
+    date: Option<String>,
+
+    /// total number of neighbors returned
+    count: usize,
+
+    /// the AS has more relationships than returned in a single response and the lists are
+    /// incomplete
+    truncated: bool,
+
+    /// providers (upstreams) of the queried AS
+    upstreams: Vec<AsNeighbor>,
+
+    /// customers (downstreams) of the queried AS
+    downstreams: Vec<AsNeighbor>,
+
+    /// peers of the queried AS
+    peers: Vec<AsNeighbor>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AsRelationshipsBulkResponse {
+    /// number of relationships returned
+    count: usize,
+
+    /// more relationships matched than returned in a single response and the list is incomplete
+    truncated: bool,
+
+    /// relationships between the requested ASNs
+    data: Vec<AsRelationship>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct AsRelationshipsQuery {
+    /// filter by relationship type, `upstream`/`provider`, `downstream`/`customer` or `peer`. use
+    /// comma to separate multiple types
+    rel_type: Option<String>,
+
+    /// date of the relationship dataset, format: YYYY-MM-DD. defaults to the latest dataset
+    date: Option<String>,
+
+    /// include neighbor AS names and organization names, default false
+    names: Option<bool>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct AsRelationshipsBulkQuery {
+    /// date of the relationship dataset, format: YYYY-MM-DD. defaults to the latest dataset
+    date: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RelType {
+    Provider,
+    Customer,
+    Peer,
+}
+
+impl FromStr for RelType {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "upstream" | "upstreams" | "provider" | "providers" => Ok(RelType::Provider),
+            "downstream" | "downstreams" | "customer" | "customers" => Ok(RelType::Customer),
+            "peer" | "peers" => Ok(RelType::Peer),
+            _ => Err(ApiError::new_bad_request(format!(
+                "unknown relationship type: {}, valid values are `upstream`, `downstream` and `peer`",
+                s
+            ))),
+        }
+    }
+}
+
+impl RelType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RelType::Provider => "provider",
+            RelType::Customer => "customer",
+            RelType::Peer => "peer",
+        }
+    }
+}
+
+impl AsRelRawEntry {
+    /// the neighbor of `asn` in this entry and its relationship to `asn`
+    fn neighbor_of(&self, asn: u32) -> (u32, RelType) {
+        match (self.asn1 == asn, self.rel) {
+            (true, -1) => (self.asn2, RelType::Customer),
+            (false, -1) => (self.asn1, RelType::Provider),
+            (true, _) => (self.asn2, RelType::Peer),
+            (false, _) => (self.asn1, RelType::Peer),
+        }
+    }
+
+    fn into_relationship(self) -> AsRelationship {
+        AsRelationship {
+            asn1: self.asn1,
+            asn2: self.asn2,
+            relationship: match self.rel {
+                -1 => "p2c".to_string(),
+                _ => "p2p".to_string(),
+            },
+            date: self.date,
+        }
+    }
+}
+
+/// Parse an optional dataset date and return the table to query along with the date filter.
+///
+/// Without a date, the `as2rel_latest` view is used.
+pub(crate) fn as2rel_table(
+    date: &Option<String>,
+) -> Result<(&'static str, Option<NaiveDate>), ApiError> {
+    match date {
+        None => Ok(("as2rel_latest", None)),
+        Some(date) => match NaiveDate::from_str(date) {
+            Ok(d) => Ok(("as2rel", Some(d))),
+            Err(_) => Err(ApiError::new_bad_request(format!(
+                "cannot parse date string: {}",
+                date
+            ))),
+        },
+    }
+}
+
+/// Fetch raw relationship entries matching the given PostgREST `or` filter.
+///
+/// At most [MAX_RELATIONSHIP_ROWS] entries are returned, along with whether more entries matched.
+pub(crate) async fn fetch_as2rel(
+    db: &Arc<BgpkitDatabase>,
+    date: &Option<String>,
+    filter: Option<String>,
+) -> Result<(Vec<AsRelRawEntry>, bool), ApiError> {
+    let (table, date) = as2rel_table(date)?;
+    let mut db_query = db.client.from(table).select("*");
+    if let Some(date) = date {
+        db_query = db_query.eq("date", date.to_string());
+    }
+    if let Some(filter) = filter {
+        db_query = db_query.or(filter);
+    }
+    // one extra row tells whether the result is truncated
+    db_query = db_query
+        .order("asn1.asc,asn2.asc")
+        .range(0, MAX_RELATIONSHIP_ROWS);
+
+    let response = execute(db_query).await?;
+    match serde_json::from_str::<Vec<AsRelRawEntry>>(response.as_str()) {
+        Ok(mut entries) => {
+            let truncated = entries.len() > MAX_RELATIONSHIP_ROWS;
+            entries.truncate(MAX_RELATIONSHIP_ROWS);
+            Ok((entries, truncated))
+        }
+        Err(_) => Err(ApiError::new_internal("cannot parse database response")),
+    }
+}
+
+/// Fetch all provider-to-customer links of a relationship dataset as `(provider, customer)` pairs,
+/// along with the dataset date.
+pub(crate) async fn fetch_p2c_edges(
+    db: &Arc<BgpkitDatabase>,
+    date: &Option<String>,
+) -> Result<(Option<String>, Vec<(u32, u32)>), ApiError> {
+    let (table, date) = as2rel_table(date)?;
+    let mut db_query = db.client.from(table).select("*").eq("rel", "-1");
+    if let Some(date) = date {
+        db_query = db_query.eq("date", date.to_string());
+    }
+    let entries: Vec<AsRelRawEntry> =
+        execute_all(db_query, "asn1.asc,asn2.asc", AS2REL_PAGE_SIZE).await?;
+    let date = entries.first().map(|e| e.date.clone());
+    let edges = entries.into_iter().map(|e| (e.asn1, e.asn2)).collect();
+    Ok((date, edges))
+}
+
+/// Upstreams, downstreams and peers of an autonomous system.
+///
+/// Relationships are inferred in the style of CAIDA's AS relationship dataset.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/as-relationships/{asn}",
+    responses(
+        (status = 200, description = "AS relationships found", body = AsRelationshipsResponse),
+        (status = 400, description = "invalid query parameters"),
+    ),
+    params(
+        ("asn" = u32, Path, description = "AS number to query relationships for"),
+        AsRelationshipsQuery,
+    )
+)]
+pub async fn search_as_relationships(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Path(asn): Path<u32>,
+    query: Query<AsRelationshipsQuery>,
+) -> Result<Json<AsRelationshipsResponse>, ApiError> {
+    let rel_types: BTreeSet<RelType> = match &query.rel_type {
+        None => [RelType::Provider, RelType::Customer, RelType::Peer]
+            .into_iter()
+            .collect(),
+        Some(types) => types
+            .split(',')
+            .map(RelType::from_str)
+            .collect::<Result<_, _>>()?,
+    };
+
+    let (entries, truncated) = fetch_as2rel(
+        &db,
+        &query.date,
+        Some(format!("asn1.eq.{},asn2.eq.{}", asn, asn)),
+    )
+    .await?;
+    let date = entries.first().map(|e| e.date.clone());
+
+    let mut neighbors: Vec<AsNeighbor> = entries
+        .iter()
+        .map(|e| (e.neighbor_of(asn), e.date.clone()))
+        .filter(|((_, rel), _)| rel_types.contains(rel))
+        .map(|((neighbor, rel), date)| AsNeighbor {
+            asn: neighbor,
+            relationship: rel.as_str().to_string(),
+            date,
+            as_name: None,
+            org_name: None,
+        })
+        .collect();
+    neighbors.sort_by_key(|n| n.asn);
+
+    if query.names.unwrap_or(false) {
+        let asns: Vec<u32> = neighbors.iter().map(|n| n.asn).collect();
+        let info = lookup_asninfo(&db, asns.as_slice()).await?;
+        for neighbor in neighbors.iter_mut() {
+            if let Some(info) = info.get(&neighbor.asn) {
+                neighbor.as_name = info.as_name.clone();
+                neighbor.org_name = info.org_name.clone();
+            }
+        }
+    }
+
+    let count = neighbors.len();
+    let (mut upstreams, mut downstreams, mut peers) = (vec![], vec![], vec![]);
+    for neighbor in neighbors {
+        match neighbor.relationship.as_str() {
+            "provider" => upstreams.push(neighbor),
+            "customer" => downstreams.push(neighbor),
+            _ => peers.push(neighbor),
+        }
+    }
+
+    Ok(Json(AsRelationshipsResponse {
+        asn,
+        date,
+        count,
+        truncated,
+        upstreams,
+        downstreams,
+        peers,
+    }))
+}
+
+/// Relationships among a set of autonomous systems.
+///
+/// The request body is either a JSON array of ASNs or a newline-delimited list of ASNs, up to
+/// 1,000 ASNs per request. Only relationships where both ASes are in the set are returned.
+#[utoipa::path(
+    post,
+    tag = "meta",
+    path = "/as-relationships/bulk",
+    request_body(content = Vec<u32>, description = "JSON array or newline-delimited list of ASNs"),
+    responses(
+        (status = 200, description = "AS relationships found", body = AsRelationshipsBulkResponse),
+        (status = 400, description = "invalid ASN list or query parameters"),
+    ),
+    params(
+        AsRelationshipsBulkQuery,
+    )
+)]
+pub async fn bulk_as_relationships(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    query: Query<AsRelationshipsBulkQuery>,
+    body: String,
+) -> Result<Json<AsRelationshipsBulkResponse>, ApiError> {
+    let asns = parse_asn_list(body.as_str())?;
+    if asns.len() > BULK_MAX_ASNS {
+        return Err(ApiError::new_bad_request(format!(
+            "too many ASNs in request: {} (max {})",
+            asns.len(),
+            BULK_MAX_ASNS
+        )));
+    }
+    if asns.is_empty() {
+        return Ok(Json(AsRelationshipsBulkResponse {
+            count: 0,
+            truncated: false,
+            data: vec![],
+        }));
+    }
+
+    // every pair of ASN batches is queried on its own, so that no request URL carries more than
+    // two batches of ASNs
+    let batches: Vec<String> = asns
+        .iter()
+        .map(|asn| asn.to_string())
+        .collect::<Vec<String>>()
+        .chunks(BULK_BATCH_SIZE)
+        .map(|batch| batch.join(","))
+        .collect();
+    let (db, date) = (&db, &query.date);
+    let lookups: Vec<_> = batches
+        .iter()
+        .flat_map(|asns1| {
+            batches.iter().map(move |asns2| {
+                fetch_as2rel(
+                    db,
+                    date,
+                    Some(format!("and(asn1.in.({}),asn2.in.({}))", asns1, asns2)),
+                )
+            })
+        })
+        .collect();
+    let results: Vec<(Vec<AsRelRawEntry>, bool)> = stream::iter(lookups)
+        .buffer_unordered(BULK_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    let mut truncated = results.iter().any(|(_, truncated)| *truncated);
+    let mut data: Vec<AsRelationship> = results
+        .into_iter()
+        .flat_map(|(entries, _)| entries)
+        .map(|entry| entry.into_relationship())
+        .collect();
+    data.sort_by_key(|r| (r.asn1, r.asn2));
+    if data.len() > MAX_RELATIONSHIP_ROWS {
+        data.truncate(MAX_RELATIONSHIP_ROWS);
+        truncated = true;
+    }
+    Ok(Json(AsRelationshipsBulkResponse {
+        count: data.len(),
+        truncated,
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_neighbor_of() {
+        let entry = AsRelRawEntry {
+            date: "2023-01-01".to_string(),
+            asn1: 3356,
+            asn2: 13335,
+            rel: -1,
+        };
+        assert_eq!(entry.neighbor_of(3356), (13335, RelType::Customer));
+        assert_eq!(entry.neighbor_of(13335), (3356, RelType::Provider));
+
+        let entry = AsRelRawEntry {
+            date: "2023-01-01".to_string(),
+            asn1: 174,
+            asn2: 3356,
+            rel: 0,
+        };
+        assert_eq!(entry.neighbor_of(3356), (174, RelType::Peer));
+    }
+}
@@ -1,43 +1,148 @@
-use crate::api::Pagination;
-use crate::db::BgpkitDatabase;
+use crate::api::{fuzzy, special_purpose_category, ApiError, Pagination};
+use crate::db::{execute, BgpkitDatabase};
 use axum::extract::Query;
 use axum::{Extension, Json};
+use futures::{stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::sync::Arc;
 use utoipa::{IntoParams, ToSchema};
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct AsnInfo {
     /// Autonomous system (AS) number
-    asn: u32,
+    pub asn: u32,
 
     /// AS name
-    as_name: Option<String>,
+    pub as_name: Option<String>,
 
     /// Organization ID based on CAIDA's as2org dataset
-    org_id: Option<String>,
+    pub org_id: Option<String>,
 
     /// Organization name based on CAIDA's as2org dataset
-    org_name: Option<String>,
+    pub org_name: Option<String>,
 
     /// Registration country in two-letter code format
-    country_code: Option<String>,
+    pub country_code: Option<String>,
 
     /// Registration country full name
-    country_name: Option<String>,
+    pub country_name: Option<String>,
 
     /// RIR source
-    data_source: Option<String>,
+    pub data_source: Option<String>,
+
+    /// special-purpose category for reserved, private-use, documentation and other special ASNs,
+    /// see `/bogons/asn` for the full registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub special_purpose: Option<String>,
+}
+
+impl AsnInfo {
+    /// placeholder entry for a special-purpose ASN without registration information
+    fn new_special_purpose(asn: u32) -> Option<Self> {
+        special_purpose_category(asn).map(|category| AsnInfo {
+            asn,
+            as_name: None,
+            org_id: None,
+            org_name: None,
+            country_code: None,
+            country_name: None,
+            data_source: None,
+            special_purpose: Some(category.to_string()),
+        })
+    }
+
+    /// fill in the special-purpose category based on the ASN
+    fn annotate(&mut self) {
+        self.special_purpose = special_purpose_category(self.asn).map(|c| c.to_string());
+    }
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct AsninfoResponse {
     page: usize,
     page_size: usize,
+
+    /// number of ASNs with registration information returned
     count: usize,
+
+    /// number of special-purpose ASNs without registration information listed in `data` in
+    /// addition to `count`
+    special_purpose: usize,
     data: Vec<AsnInfo>,
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AsninfoBulkResponse {
+    /// number of unique ASNs requested
+    requested: usize,
+
+    /// number of ASNs with registration information found
+    count: usize,
+
+    /// number of special-purpose ASNs without registration information listed in `data` in
+    /// addition to `count`
+    special_purpose: usize,
+
+    /// ASN information keyed by ASN, including special-purpose ASNs without registration information
+    data: BTreeMap<u32, AsnInfo>,
+
+    /// requested ASNs without any information found
+    unknown: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AsnInfoMatch {
+    /// relevance score between 0 and 1, higher is better
+    score: f64,
+
+    /// the field that matched the search best, `as_name` or `org_name`
+    matched_field: String,
+
+    #[serde(flatten)]
+    info: AsnInfo,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AsninfoRankedResponse {
+    count: usize,
+    data: Vec<AsnInfoMatch>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct AsninfoRankedSearchQuery {
+    /// search term to match against AS names and/or organization names
+    q: String,
+
+    /// the fields to match against: `name` for AS name only, `org` for organization name only, or
+    /// `all` (default) for both
+    field: Option<String>,
+
+    /// filter by two-letter country code or country name
+    country: Option<String>,
+
+    /// minimum relevance score between 0 and 1 for results to be included, default 0.2
+    min_score: Option<f64>,
+
+    /// maximum number of results to return, default 20, maximum 1000
+    limit: Option<usize>,
+}
+
+/// maximum number of candidates fetched from database for each stage of ranked search
+const RANKED_MAX_CANDIDATES: usize = 2000;
+
+/// maximum search term length to expand into typo-tolerant candidate patterns
+const RANKED_MAX_TYPO_QUERY_LEN: usize = 32;
+
+/// maximum number of ASNs accepted by a single bulk request
+const BULK_MAX_ASNS: usize = 50_000;
+
+/// number of ASNs sent upstream in each batched query
+const BULK_BATCH_SIZE: usize = 500;
+
+/// number of batched queries in flight at once during a bulk lookup
+const BULK_CONCURRENCY: usize = 8;
+
 #[derive(Deserialize, IntoParams, Debug)]
 pub struct AsninfoSearchQuery {
     /// filter results by ASN exact match
@@ -46,7 +151,8 @@ pub struct AsninfoSearchQuery {
     /// filter results that has asn in the specified array, formatted as ','-separated string
     asns: Option<String>,
 
-    /// filter results by AS name or organization name
+    /// filter results by AS name or organization name, case-insensitive substring match. results are
+    /// not ranked by relevance, use `/asninfo/search` for ranked and typo-tolerant name search
     name: Option<String>,
 
     /// filter by two-letter country code or country name
@@ -103,13 +209,356 @@ pub async fn search_asninfo(
     db_query = db_query.range(low, high);
 
     let response = db_query.execute().await.unwrap();
-    let data: Vec<AsnInfo> = serde_json::from_str(response.text().await.unwrap().as_str()).unwrap();
+    let mut data: Vec<AsnInfo> =
+        serde_json::from_str(response.text().await.unwrap().as_str()).unwrap();
+    data.iter_mut().for_each(|info| info.annotate());
+
+    // special-purpose ASNs have no registration information, list them explicitly on the first
+    // page instead of returning nothing
     let count = data.len();
+    if page == 0 {
+        let mut requested: Vec<u32> = query.asn.into_iter().collect();
+        if let Some(asns_str) = &query.asns {
+            requested.extend(
+                asns_str
+                    .split(',')
+                    .filter_map(|a| a.trim().parse::<u32>().ok()),
+            );
+        }
+        for asn in requested {
+            if data.iter().all(|info| info.asn != asn) {
+                if let Some(info) = AsnInfo::new_special_purpose(asn) {
+                    data.push(info);
+                }
+            }
+        }
+    }
     let response = AsninfoResponse {
         page,
         page_size,
         count,
+        special_purpose: data.len() - count,
         data,
     };
     Json(response)
 }
+
+/// Fetch ranked search candidates matching any of the given conditions, ordered by ASN.
+async fn fetch_candidates(
+    db: &Arc<BgpkitDatabase>,
+    conditions: &[String],
+    country: &Option<String>,
+) -> Result<Vec<AsnInfo>, ApiError> {
+    let mut db_query = db
+        .client
+        .from("asn_view")
+        .select("*")
+        .or(conditions.join(","));
+
+    if let Some(country) = country {
+        db_query = db_query.or(format!(
+            r#"country_code.ilike."{}", country_name.ilike."*{}*""#,
+            country, country
+        ));
+    }
+    db_query = db_query
+        .order("asn.asc")
+        .range(0, RANKED_MAX_CANDIDATES - 1);
+
+    let response = execute(db_query).await?;
+    match serde_json::from_str(response.as_str()) {
+        Ok(candidates) => Ok(candidates),
+        Err(_) => Err(ApiError::new_internal("cannot parse database response")),
+    }
+}
+
+/// Search for autonomous systems by name, ranked by relevance.
+///
+/// Exact name matches rank first, followed by prefix matches, substring matches, matches with
+/// small typos, and finally names with high trigram similarity. Each result carries its relevance
+/// `score` and the field that matched.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/asninfo/search",
+    responses(
+        (status = 200, description = "ranked ASN information found", body = AsninfoRankedResponse),
+        (status = 400, description = "invalid search parameters"),
+    ),
+    params(
+        AsninfoRankedSearchQuery,
+    )
+)]
+pub async fn ranked_search_asninfo(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    query: Query<AsninfoRankedSearchQuery>,
+) -> Result<Json<AsninfoRankedResponse>, ApiError> {
+    let fields: Vec<&str> = match query.field.as_deref().map(|f| f.to_lowercase()) {
+        None => vec!["as_name", "org_name"],
+        Some(f) => match f.as_str() {
+            "all" | "both" => vec!["as_name", "org_name"],
+            "name" | "as_name" => vec!["as_name"],
+            "org" | "org_name" => vec!["org_name"],
+            _ => {
+                return Err(ApiError::new_bad_request(format!(
+                    "unknown search field: {}, valid values are `name`, `org` and `all`",
+                    f
+                )))
+            }
+        },
+    };
+
+    let term = fuzzy::normalize(query.q.as_str());
+    if term.is_empty() {
+        return Err(ApiError::new_bad_request(
+            "search term must contain at least one letter or digit",
+        ));
+    }
+
+    let min_score = query.min_score.unwrap_or(0.2);
+    if !(0.0..=1.0).contains(&min_score) {
+        return Err(ApiError::new_bad_request(
+            "min_score must be between 0 and 1",
+        ));
+    }
+
+    // exact and prefix matches are fetched on their own first, so a flood of fuzzy matches
+    // cannot push the best candidates past the candidate limit
+    let like_term = term.replace(' ', "*");
+    let leading: Vec<String> = fields
+        .iter()
+        .map(|field| format!(r#"{}.ilike."{}*""#, field, like_term))
+        .collect();
+    let mut candidates = fetch_candidates(&db, &leading, &query.country).await?;
+
+    // fuzzy candidates: substring matches of the search term, plus every one-edit variant for
+    // typo tolerance
+    let mut patterns = vec![like_term.clone()];
+    let term_len = like_term.chars().count();
+    if fuzzy::max_typos(term_len) > 0 && term_len <= RANKED_MAX_TYPO_QUERY_LEN {
+        patterns.extend(fuzzy::one_edit_patterns(like_term.as_str()));
+    }
+    let fuzzy_conditions: Vec<String> = fields
+        .iter()
+        .flat_map(|field| {
+            patterns
+                .iter()
+                .map(move |p| format!(r#"{}.ilike."*{}*""#, field, p))
+        })
+        .collect();
+    let seen: HashSet<u32> = candidates.iter().map(|info| info.asn).collect();
+    candidates.extend(
+        fetch_candidates(&db, &fuzzy_conditions, &query.country)
+            .await?
+            .into_iter()
+            .filter(|info| !seen.contains(&info.asn)),
+    );
+    candidates.iter_mut().for_each(|info| info.annotate());
+
+    let limit = query.limit.unwrap_or(20).min(1000);
+    let mut data: Vec<AsnInfoMatch> = candidates
+        .into_iter()
+        .filter_map(|info| {
+            let (score, matched_field) = fields
+                .iter()
+                .map(|field| {
+                    let value = match *field {
+                        "as_name" => info.as_name.as_deref(),
+                        _ => info.org_name.as_deref(),
+                    };
+                    (
+                        fuzzy::score(term.as_str(), value.unwrap_or_default()),
+                        *field,
+                    )
+                })
+                .fold((0.0, ""), |best, cur| match cur.0 > best.0 {
+                    true => cur,
+                    false => best,
+                });
+            match score >= min_score && score > 0.0 {
+                true => Some(AsnInfoMatch {
+                    score,
+                    matched_field: matched_field.to_string(),
+                    info,
+                }),
+                false => None,
+            }
+        })
+        .collect();
+    data.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.info.asn.cmp(&b.info.asn))
+    });
+    data.truncate(limit);
+
+    Ok(Json(AsninfoRankedResponse {
+        count: data.len(),
+        data,
+    }))
+}
+
+/// Look up information for a list of ASNs, split into concurrent upstream batches.
+///
+/// ASNs without any information are absent from the returned map.
+pub(crate) async fn lookup_asninfo(
+    db: &Arc<BgpkitDatabase>,
+    asns: &[u32],
+) -> Result<BTreeMap<u32, AsnInfo>, ApiError> {
+    let lookups: Vec<_> = asns
+        .chunks(BULK_BATCH_SIZE)
+        .map(|batch| fetch_asninfo_batch(db.clone(), batch.to_vec()))
+        .collect();
+    let batches: Vec<Vec<AsnInfo>> = stream::iter(lookups)
+        .buffer_unordered(BULK_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    let mut data: BTreeMap<u32, AsnInfo> = BTreeMap::new();
+    for mut entry in batches.into_iter().flatten() {
+        entry.annotate();
+        data.insert(entry.asn, entry);
+    }
+    Ok(data)
+}
+
+/// Information for one batch of ASNs.
+async fn fetch_asninfo_batch(
+    db: Arc<BgpkitDatabase>,
+    batch: Vec<u32>,
+) -> Result<Vec<AsnInfo>, ApiError> {
+    let db_query = db
+        .client
+        .from("asn_view")
+        .select("*")
+        .in_("asn", batch.iter().map(|asn| asn.to_string()))
+        .range(0, batch.len() - 1);
+    let text = execute(db_query).await?;
+    match serde_json::from_str(text.as_str()) {
+        Ok(entries) => Ok(entries),
+        Err(_) => Err(ApiError::new_internal("cannot parse database response")),
+    }
+}
+
+/// Parse a single ASN, with an optional case-insensitive `AS` prefix.
+pub(crate) fn parse_asn(item: &str) -> Option<u32> {
+    let asn_str = match item.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("as") => &item[2..],
+        _ => item,
+    };
+    asn_str.parse::<u32>().ok()
+}
+
+/// Parse a list of ASNs from either a JSON array or a newline-delimited list.
+///
+/// Commas and other whitespace are also accepted as separators in the plain-text form, and an
+/// optional case-insensitive `AS` prefix is stripped from each entry. Also used for the
+/// comma-separated `asn` query parameters.
+pub(crate) fn parse_asn_list(body: &str) -> Result<BTreeSet<u32>, ApiError> {
+    let body = body.trim();
+    if body.starts_with('[') {
+        return match serde_json::from_str::<Vec<u32>>(body) {
+            Ok(asns) => Ok(asns.into_iter().collect()),
+            Err(e) => Err(ApiError::new_bad_request(format!(
+                "cannot parse JSON array of ASNs: {}",
+                e
+            ))),
+        };
+    }
+
+    let mut asns = BTreeSet::new();
+    for item in body
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+    {
+        match parse_asn(item) {
+            Some(asn) => {
+                asns.insert(asn);
+            }
+            None => {
+                return Err(ApiError::new_bad_request(format!(
+                    "cannot parse ASN: {}",
+                    item
+                )))
+            }
+        }
+    }
+    Ok(asns)
+}
+
+/// Bulk lookup of information for a large list of autonomous systems.
+///
+/// The request body is either a JSON array of ASNs (e.g. `[13335, 15169]`) or a newline-delimited
+/// list of ASNs. Up to 50,000 ASNs are accepted per request.
+#[utoipa::path(
+    post,
+    tag = "meta",
+    path = "/asninfo/bulk",
+    request_body(content = Vec<u32>, description = "JSON array or newline-delimited list of ASNs"),
+    responses(
+        (status = 200, description = "ASN information found", body = AsninfoBulkResponse),
+        (status = 400, description = "malformed ASN list"),
+    )
+)]
+pub async fn bulk_asninfo(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    body: String,
+) -> Result<Json<AsninfoBulkResponse>, ApiError> {
+    let asns = parse_asn_list(body.as_str())?;
+    if asns.len() > BULK_MAX_ASNS {
+        return Err(ApiError::new_bad_request(format!(
+            "too many ASNs in request: {} (max {})",
+            asns.len(),
+            BULK_MAX_ASNS
+        )));
+    }
+
+    let asn_vec: Vec<u32> = asns.iter().cloned().collect();
+    let mut data = lookup_asninfo(&db, asn_vec.as_slice()).await?;
+    let count = data.len();
+    for asn in asns.iter() {
+        if !data.contains_key(asn) {
+            if let Some(info) = AsnInfo::new_special_purpose(*asn) {
+                data.insert(*asn, info);
+            }
+        }
+    }
+
+    let unknown: Vec<u32> = asns
+        .iter()
+        .filter(|asn| !data.contains_key(asn))
+        .cloned()
+        .collect();
+
+    Ok(Json(AsninfoBulkResponse {
+        requested: asns.len(),
+        count,
+        special_purpose: data.len() - count,
+        data,
+        unknown,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_asn_list() {
+        let asns = parse_asn_list("[13335, 15169, 13335]").unwrap();
+        assert_eq!(asns.into_iter().collect::<Vec<u32>>(), vec![13335, 15169]);
+
+        let asns = parse_asn_list("13335\nAS15169\r\n400644,\n").unwrap();
+        assert_eq!(
+            asns.into_iter().collect::<Vec<u32>>(),
+            vec![13335, 15169, 400644]
+        );
+
+        let asns = parse_asn_list("AS13335, 64500,as13335").unwrap();
+        assert_eq!(asns.into_iter().collect::<Vec<u32>>(), vec![13335, 64500]);
+
+        assert!(parse_asn_list("13335\nfoo").is_err());
+        assert!(parse_asn_list("ASAS13335").is_err());
+        assert!(parse_asn_list("[13335, -1]").is_err());
+    }
+}
@@ -0,0 +1,496 @@
+use crate::api::{
+    merge_date_ranges, parse_as_of, parse_date_range, parse_query_date, parse_tals, ApiError,
+    Pagination, DEFAULT_MERGE_GAPS,
+};
+use crate::cache::TtlCache;
+use crate::db::{execute, fetch_pages, require_feature, BgpkitDatabase, DbFeature};
+use axum::extract::Query;
+use axum::{Extension, Json};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+/// number of ASPAs fetched per request when collecting all matching ASPAs
+const QUERY_ASPA_PAGE_SIZE: usize = 10_000;
+
+/// maximum number of ASNs in a verified AS path
+const MAX_AS_PATH_LEN: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct AspaEntry {
+    /// customer Autonomous system (AS) number
+    pub customer_asn: u32,
+
+    /// authorized provider ASNs
+    pub providers: Vec<u32>,
+
+    /// trust anchor locator
+    pub tal: String,
+
+    /// the ASPA is valid on the `as_of` date, by default the previous day UTC.
+    pub current: bool,
+
+    /// ASPA valid date ranges
+    pub date_ranges: Vec<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AspaRawEntry {
+    /// customer Autonomous system (AS) number
+    pub customer_asn: u32,
+
+    /// authorized provider ASNs
+    pub providers: Vec<u32>,
+
+    /// trust anchor locator
+    pub tal: String,
+
+    /// ASPA valid date ranges
+    pub date_ranges: Vec<String>,
+}
+
+impl AspaRawEntry {
+    /// process raw ASPA database query results, merging single-day gaps in the valid date ranges
+    ///
+    /// the ASPA is marked current if it is valid on `as_of`
+    fn into_aspa_entry(self, as_of: NaiveDate) -> AspaEntry {
+        let ranges: Vec<(NaiveDate, NaiveDate)> = self
+            .date_ranges
+            .iter()
+            .filter_map(|r| parse_date_range(r.as_str()))
+            .collect();
+        let current = ranges
+            .iter()
+            .any(|(first, last)| *first <= as_of && as_of <= *last);
+        let (ranges, _) = merge_date_ranges(ranges, DEFAULT_MERGE_GAPS);
+
+        AspaEntry {
+            customer_asn: self.customer_asn,
+            providers: self.providers,
+            tal: self.tal,
+            current,
+            date_ranges: ranges
+                .into_iter()
+                .map(|(first, last)| vec![first.to_string(), last.to_string()])
+                .collect(),
+        }
+    }
+}
+
+/// Parameters of the `query_aspa_history` database function.
+///
+/// Empty strings and `-1` disable the corresponding filters.
+#[derive(Serialize, Debug, Clone)]
+struct QueryAspaParams {
+    res_limit: usize,
+    res_offset: usize,
+    customer_asn: i64,
+    provider_asn: i64,
+    nic: String,
+    date: String,
+
+    /// multiple TALs, replacing `nic`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nics: Option<Vec<String>>,
+}
+
+impl QueryAspaParams {
+    fn new(res_limit: usize, res_offset: usize) -> Self {
+        QueryAspaParams {
+            res_limit,
+            res_offset,
+            customer_asn: -1,
+            provider_asn: -1,
+            nic: "".to_string(),
+            date: "".to_string(),
+            nics: None,
+        }
+    }
+
+    /// filter by canonical TAL names, no filter if empty
+    fn set_tals(&mut self, tals: Vec<String>) {
+        match tals.len() {
+            0 => {}
+            1 => self.nic = tals[0].clone(),
+            _ => self.nics = Some(tals),
+        }
+    }
+}
+
+/// Call the `query_aspa_history` database function and parse the raw ASPA entries.
+async fn query_aspa_history(
+    db: &Arc<BgpkitDatabase>,
+    params: &QueryAspaParams,
+) -> Result<Vec<AspaRawEntry>, ApiError> {
+    require_feature(DbFeature::AspaHistory)?;
+    let query_string = match serde_json::to_string(params) {
+        Ok(s) => s,
+        Err(_) => return Err(ApiError::new_internal("cannot construct ASPA query")),
+    };
+    info!("{}", &query_string);
+
+    let response = execute(db.client.rpc("query_aspa_history", query_string)).await?;
+    match serde_json::from_str(response.as_str()) {
+        Ok(data) => Ok(data),
+        Err(_) => Err(ApiError::new_internal("cannot parse database response")),
+    }
+}
+
+/// All ASPAs valid on a date.
+pub struct AspaSet {
+    /// union of the authorized providers, by customer ASN
+    providers: HashMap<u32, BTreeSet<u32>>,
+}
+
+/// Collect all ASPAs valid on `date`, caching them on first use.
+async fn load_aspa_set(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<AspaSet>,
+    date: NaiveDate,
+) -> Result<Arc<AspaSet>, ApiError> {
+    cache
+        .get_or_try_insert_with(date.to_string().as_str(), || async {
+            let entries = fetch_pages(QUERY_ASPA_PAGE_SIZE, None, |offset, limit| {
+                let mut params = QueryAspaParams::new(limit, offset);
+                params.date = date.to_string();
+                async move { query_aspa_history(db, &params).await }
+            })
+            .await?;
+            let mut providers: HashMap<u32, BTreeSet<u32>> = HashMap::new();
+            for entry in entries {
+                providers
+                    .entry(entry.customer_asn)
+                    .or_default()
+                    .extend(entry.providers);
+            }
+            Ok(AspaSet { providers })
+        })
+        .await
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AspasResponse {
+    page: usize,
+    page_size: usize,
+    count: usize,
+    data: Vec<AspaEntry>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct AspasSearchQuery {
+    /// filter results by customer ASN
+    customer_asn: Option<u32>,
+
+    /// filter results to ASPAs authorizing this provider ASN
+    provider_asn: Option<u32>,
+
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// limit the date of the ASPAs, format: YYYY-MM-DD, e.g. `?date=2024-01-01`
+    date: Option<String>,
+
+    /// reference date for the `current` flag of the results, format: YYYY-MM-DD. defaults to the
+    /// previous day UTC
+    as_of: Option<String>,
+}
+
+/// Search ASPA (Autonomous System Provider Authorization) history.
+///
+/// Returns the customer ASN, authorized providers, trust anchor and valid date ranges of each ASPA,
+/// in the same shape as ROAs.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/aspas",
+    responses(
+        (status = 200, description = "ASPAs found", body = AspasResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        AspasSearchQuery,
+        Pagination
+    )
+)]
+pub async fn search_aspas(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    query: Query<AspasSearchQuery>,
+    pagination: Query<Pagination>,
+) -> Result<Json<AspasResponse>, ApiError> {
+    let (page, page_size) = pagination.extract(1000);
+    let as_of = parse_as_of(&query.as_of)?;
+
+    let mut params = QueryAspaParams::new(page_size, page * page_size);
+    if let Some(customer_asn) = &query.customer_asn {
+        params.customer_asn = *customer_asn as i64;
+    }
+    if let Some(provider_asn) = &query.provider_asn {
+        params.provider_asn = *provider_asn as i64;
+    }
+    params.set_tals(parse_tals(&query.tal)?);
+    if let Some(date) = &query.date {
+        params.date = parse_query_date(date)?.to_string();
+    }
+
+    let data: Vec<AspaEntry> = query_aspa_history(&db, &params)
+        .await?
+        .into_iter()
+        .map(|entry| entry.into_aspa_entry(as_of))
+        .collect();
+
+    Ok(Json(AspasResponse {
+        page,
+        page_size,
+        count: data.len(),
+        data,
+    }))
+}
+
+/// Result of an ASPA hop check.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AspaHopResult {
+    /// the provider ASN is authorized by the customer's ASPA
+    Provider,
+
+    /// the customer has an ASPA that does not authorize the provider ASN
+    NotProvider,
+
+    /// the customer has no ASPA
+    NoAttestation,
+}
+
+/// ASPA verification state of an AS path.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AspaState {
+    Valid,
+    Invalid,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct AspaHop {
+    customer_asn: u32,
+    provider_asn: u32,
+    result: AspaHopResult,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AspaVerifyRequest {
+    /// AS path as received, the neighbor ASN first and the origin ASN last
+    as_path: Vec<u32>,
+
+    /// `upstream` (default) for routes received from a customer or peer, `downstream` for routes
+    /// received from a provider
+    direction: Option<String>,
+
+    /// date of the ASPA set, format: YYYY-MM-DD. defaults to the previous day UTC
+    date: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AspaVerifyResponse {
+    date: String,
+    direction: String,
+
+    /// AS path with prepends removed, origin ASN last
+    as_path: Vec<u32>,
+    state: AspaState,
+
+    /// hop checks of adjacent ASNs, in both directions for downstream verification
+    hops: Vec<AspaHop>,
+}
+
+/// check whether `provider` is an authorized provider of `customer`
+fn hop_check(aspas: &HashMap<u32, BTreeSet<u32>>, customer: u32, provider: u32) -> AspaHopResult {
+    match aspas.get(&customer) {
+        None => AspaHopResult::NoAttestation,
+        Some(providers) if providers.contains(&provider) => AspaHopResult::Provider,
+        Some(_) => AspaHopResult::NotProvider,
+    }
+}
+
+/// Verify an AS path against a set of ASPAs.
+///
+/// `path` is ordered from the origin to the neighbor ASN, without prepends. For upstream paths the
+/// whole path must be an up-ramp, for downstream paths an up-ramp followed by a down-ramp.
+fn verify_as_path(
+    aspas: &HashMap<u32, BTreeSet<u32>>,
+    path: &[u32],
+    downstream: bool,
+) -> (AspaState, Vec<AspaHop>) {
+    let n = path.len();
+    if n == 0 {
+        return (AspaState::Invalid, vec![]);
+    }
+
+    // up[i]: whether path[i + 1] is a provider of path[i]
+    let up: Vec<AspaHopResult> = path
+        .windows(2)
+        .map(|w| hop_check(aspas, w[0], w[1]))
+        .collect();
+    // down[i]: whether path[i] is a provider of path[i + 1]
+    let down: Vec<AspaHopResult> = path
+        .windows(2)
+        .map(|w| hop_check(aspas, w[1], w[0]))
+        .collect();
+
+    let mut hops: Vec<AspaHop> = path
+        .windows(2)
+        .zip(up.iter())
+        .map(|(w, result)| AspaHop {
+            customer_asn: w[0],
+            provider_asn: w[1],
+            result: *result,
+        })
+        .collect();
+
+    // length of the up-ramp from the origin, stopping at the first hop failing `stop`
+    let up_ramp = |stop: &dyn Fn(AspaHopResult) -> bool| match up.iter().position(|r| stop(*r)) {
+        Some(i) => i + 1,
+        None => n,
+    };
+    // length of the down-ramp from the neighbor, stopping at the first hop failing `stop`
+    let down_ramp = |stop: &dyn Fn(AspaHopResult) -> bool| match down.iter().rposition(|r| stop(*r))
+    {
+        Some(i) => n - i - 1,
+        None => n,
+    };
+    let not_provider = |r: AspaHopResult| r == AspaHopResult::NotProvider;
+    let not_attested = |r: AspaHopResult| r != AspaHopResult::Provider;
+
+    let state = match downstream {
+        false => {
+            if up_ramp(&not_provider) < n {
+                AspaState::Invalid
+            } else if up_ramp(&not_attested) < n {
+                AspaState::Unknown
+            } else {
+                AspaState::Valid
+            }
+        }
+        true => {
+            hops.extend(path.windows(2).zip(down.iter()).map(|(w, result)| AspaHop {
+                customer_asn: w[1],
+                provider_asn: w[0],
+                result: *result,
+            }));
+            if n <= 2 {
+                AspaState::Valid
+            } else if up_ramp(&not_provider) + down_ramp(&not_provider) < n {
+                AspaState::Invalid
+            } else if up_ramp(&not_attested) + down_ramp(&not_attested) < n {
+                AspaState::Unknown
+            } else {
+                AspaState::Valid
+            }
+        }
+    };
+    (state, hops)
+}
+
+/// Verify an AS path with ASPA.
+///
+/// Checks the AS path against the ASPAs valid on the given date, following the upstream or
+/// downstream verification procedure depending on where the route was received from.
+#[utoipa::path(
+    post,
+    tag = "bgp",
+    path = "/aspa/verify",
+    request_body = AspaVerifyRequest,
+    responses(
+        (status = 200, description = "ASPA verification result", body = AspaVerifyResponse),
+        (status = 400, description = "invalid AS path, direction or date"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+)]
+pub async fn verify_aspa(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<AspaSet>>>,
+    Json(request): Json<AspaVerifyRequest>,
+) -> Result<Json<AspaVerifyResponse>, ApiError> {
+    let downstream = match request
+        .direction
+        .as_deref()
+        .map(|d| d.trim().to_lowercase())
+    {
+        None => false,
+        Some(d) if d == "upstream" => false,
+        Some(d) if d == "downstream" => true,
+        Some(d) => {
+            return Err(ApiError::new_bad_request(format!(
+                "unknown direction: {}, valid values are `upstream` and `downstream`",
+                d
+            )))
+        }
+    };
+    if request.as_path.is_empty() || request.as_path.len() > MAX_AS_PATH_LEN {
+        return Err(ApiError::new_bad_request(format!(
+            "AS path must contain between 1 and {} ASNs",
+            MAX_AS_PATH_LEN
+        )));
+    }
+    let date = parse_as_of(&request.date)?;
+
+    // remove prepends and order from the origin
+    let mut path = request.as_path.clone();
+    path.dedup();
+    path.reverse();
+
+    let aspas = load_aspa_set(&db, &cache, date).await?;
+    let (state, hops) = verify_as_path(&aspas.providers, &path, downstream);
+
+    Ok(Json(AspaVerifyResponse {
+        date: date.to_string(),
+        direction: match downstream {
+            true => "downstream".to_string(),
+            false => "upstream".to_string(),
+        },
+        as_path: path.into_iter().rev().collect(),
+        state,
+        hops,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_as_path() {
+        // AS64500 and AS64501 are customers of AS64510, AS64510 and AS64511 are customers of
+        // AS64520, AS64503 is a customer of AS64511, AS64502 has no ASPA
+        let aspas: HashMap<u32, BTreeSet<u32>> = HashMap::from([
+            (64500, BTreeSet::from([64510])),
+            (64501, BTreeSet::from([64510])),
+            (64503, BTreeSet::from([64511])),
+            (64510, BTreeSet::from([64520])),
+            (64511, BTreeSet::from([64520])),
+            (64520, BTreeSet::new()),
+        ]);
+        let verify = |path: &[u32], downstream| verify_as_path(&aspas, path, downstream).0;
+
+        // up-ramp only
+        assert_eq!(verify(&[64500, 64510, 64520], false), AspaState::Valid);
+        // route leak: AS64510 sends a route from its provider to another provider
+        assert_eq!(verify(&[64520, 64510, 64530], false), AspaState::Invalid);
+        // missing attestation
+        assert_eq!(verify(&[64502, 64510], false), AspaState::Unknown);
+
+        // up-ramp then down-ramp
+        assert_eq!(verify(&[64500, 64510, 64501], true), AspaState::Valid);
+        assert_eq!(
+            verify(&[64500, 64510, 64520, 64511, 64503], true),
+            AspaState::Valid
+        );
+        // valley: AS64500 sends a route from its provider to a non-provider
+        assert_eq!(verify(&[64510, 64500, 64511], true), AspaState::Invalid);
+        assert_eq!(verify(&[64502, 64503, 64504], true), AspaState::Unknown);
+    }
+}
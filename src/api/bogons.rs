@@ -0,0 +1,231 @@
+use axum::extract::Path;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// An entry of the built-in special-purpose ASN registry.
+struct SpecialPurposeRange {
+    start: u32,
+    end: u32,
+    category: &'static str,
+    description: &'static str,
+    reference: &'static str,
+    /// whether the ASN should never appear in a public AS path
+    bogon: bool,
+}
+
+/// Special-purpose and reserved AS numbers, based on the IANA special-purpose AS numbers registry
+/// and the IANA-reserved 32-bit range that has not been made available to RIRs.
+const SPECIAL_PURPOSE_RANGES: &[SpecialPurposeRange] = &[
+    SpecialPurposeRange {
+        start: 0,
+        end: 0,
+        category: "reserved",
+        description: "Reserved AS number, may not be used in routing",
+        reference: "RFC 7607",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 112,
+        end: 112,
+        category: "as112",
+        description: "Used by the AS112 project to sink misdirected DNS queries",
+        reference: "RFC 7534",
+        bogon: false,
+    },
+    SpecialPurposeRange {
+        start: 23456,
+        end: 23456,
+        category: "as_trans",
+        description: "AS_TRANS, placeholder for 4-byte ASNs in 2-byte AS paths",
+        reference: "RFC 6793",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 64496,
+        end: 64511,
+        category: "documentation",
+        description: "Reserved for use in documentation and sample code",
+        reference: "RFC 5398",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 64512,
+        end: 65534,
+        category: "private_use",
+        description: "Reserved for private use",
+        reference: "RFC 6996",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 65535,
+        end: 65535,
+        category: "reserved",
+        description: "Reserved last 16-bit AS number",
+        reference: "RFC 7300",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 65536,
+        end: 65551,
+        category: "documentation",
+        description: "Reserved for use in documentation and sample code",
+        reference: "RFC 5398",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 65552,
+        end: 131071,
+        category: "iana_reserved",
+        description: "Reserved by IANA",
+        reference: "IANA",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 4200000000,
+        end: 4294967294,
+        category: "private_use",
+        description: "Reserved for private use",
+        reference: "RFC 6996",
+        bogon: true,
+    },
+    SpecialPurposeRange {
+        start: 4294967295,
+        end: 4294967295,
+        category: "reserved",
+        description: "Reserved last 32-bit AS number",
+        reference: "RFC 7300",
+        bogon: true,
+    },
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SpecialPurposeAsn {
+    /// first AS number of the special-purpose range
+    start: u32,
+
+    /// last AS number of the special-purpose range
+    end: u32,
+
+    /// category: `reserved`, `as112`, `as_trans`, `documentation`, `private_use` or
+    /// `iana_reserved`
+    category: String,
+
+    /// human-readable description of the range
+    description: String,
+
+    /// document defining the range
+    reference: String,
+
+    /// whether ASNs in this range are bogons, i.e. should never appear in a public AS path
+    bogon: bool,
+}
+
+impl From<&SpecialPurposeRange> for SpecialPurposeAsn {
+    fn from(range: &SpecialPurposeRange) -> Self {
+        SpecialPurposeAsn {
+            start: range.start,
+            end: range.end,
+            category: range.category.to_string(),
+            description: range.description.to_string(),
+            reference: range.reference.to_string(),
+            bogon: range.bogon,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BogonAsnResponse {
+    /// Autonomous system (AS) number
+    asn: u32,
+
+    /// whether the ASN is a bogon, i.e. should never appear in a public AS path
+    bogon: bool,
+
+    /// special-purpose registry entry covering the ASN, empty for regular ASNs
+    special_purpose: Option<SpecialPurposeAsn>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BogonAsnListResponse {
+    count: usize,
+    data: Vec<SpecialPurposeAsn>,
+}
+
+fn special_purpose_range(asn: u32) -> Option<&'static SpecialPurposeRange> {
+    SPECIAL_PURPOSE_RANGES
+        .iter()
+        .find(|range| range.start <= asn && asn <= range.end)
+}
+
+/// Special-purpose category of an ASN, e.g. `private_use`, or `None` for regular ASNs.
+pub fn special_purpose_category(asn: u32) -> Option<&'static str> {
+    special_purpose_range(asn).map(|range| range.category)
+}
+
+/// Whether an ASN is a bogon, i.e. should never appear in a public AS path.
+pub fn is_bogon_asn(asn: u32) -> bool {
+    special_purpose_range(asn)
+        .map(|range| range.bogon)
+        .unwrap_or(false)
+}
+
+/// Check whether an AS number is a bogon or otherwise special-purpose ASN.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/bogons/asn/{asn}",
+    responses(
+        (status = 200, description = "bogon classification of the ASN", body = BogonAsnResponse),
+    ),
+    params(
+        ("asn" = u32, Path, description = "AS number to check"),
+    )
+)]
+pub async fn search_bogon_asn(Path(asn): Path<u32>) -> Json<BogonAsnResponse> {
+    Json(BogonAsnResponse {
+        asn,
+        bogon: is_bogon_asn(asn),
+        special_purpose: special_purpose_range(asn).map(SpecialPurposeAsn::from),
+    })
+}
+
+/// List the built-in special-purpose ASN registry.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/bogons/asn",
+    responses(
+        (status = 200, description = "special-purpose ASN ranges", body = BogonAsnListResponse),
+    )
+)]
+pub async fn list_bogon_asns() -> Json<BogonAsnListResponse> {
+    let data: Vec<SpecialPurposeAsn> = SPECIAL_PURPOSE_RANGES
+        .iter()
+        .map(SpecialPurposeAsn::from)
+        .collect();
+    Json(BogonAsnListResponse {
+        count: data.len(),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_special_purpose_asns() {
+        assert_eq!(special_purpose_category(0), Some("reserved"));
+        assert_eq!(special_purpose_category(23456), Some("as_trans"));
+        assert_eq!(special_purpose_category(64500), Some("documentation"));
+        assert_eq!(special_purpose_category(65000), Some("private_use"));
+        assert_eq!(special_purpose_category(4200000001), Some("private_use"));
+        assert_eq!(special_purpose_category(13335), None);
+
+        assert!(is_bogon_asn(65534));
+        assert!(is_bogon_asn(100000));
+        assert!(!is_bogon_asn(112));
+        assert!(!is_bogon_asn(400644));
+    }
+}
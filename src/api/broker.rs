@@ -1,27 +1,39 @@
 use crate::api::error::ApiError;
-use crate::api::Pagination;
-use crate::db::{execute, BgpkitDatabase};
+use crate::api::{
+    collector_registry, parse_collectors, parse_project, BrokerLatestSet, Pagination,
+};
+use crate::cache::TtlCache;
+use crate::db::{execute, execute_all, execute_with_count, BgpkitDatabase};
 use axum::extract::Query;
 use axum::{Extension, Json};
 use chrono::prelude::*;
 use chrono::Duration;
+use postgrest::Builder;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use std::sync::Arc;
 use tracing::info;
 use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct BrokerEntry {
-    ts_start: String,
-    ts_end: String,
+    pub ts_start: String,
+    pub ts_end: String,
 
-    project: String,
-    collector: String,
+    pub project: String,
+    pub collector: String,
 
-    data_type: String,
-    url: String,
-    size: u32,
+    pub data_type: String,
+    pub url: String,
+
+    /// approximate file size in bytes, same as `rough_size`
+    pub size: u64,
+
+    /// approximate file size in bytes, available for all files
+    pub rough_size: u64,
+
+    /// exact file size in bytes, `0` if not yet known
+    pub exact_size: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
@@ -31,16 +43,16 @@ pub struct BrokerRawEntry {
     collector_id: String,
     data_type: String,
     url: String,
-    rough_size: u32,
-    exact_size: u32,
+    rough_size: u64,
+    exact_size: u64,
 }
 
 impl BrokerRawEntry {
-    fn to_entry(self) -> BrokerEntry {
-        let project = match self.collector_id.contains("rrc") {
-            true => "riperis".to_string(),
-            false => "route-views".to_string(),
-        };
+    pub(crate) fn into_entry(self) -> BrokerEntry {
+        let project = collector_registry()
+            .project_of(self.collector_id.as_str())
+            .unwrap_or("unknown")
+            .to_string();
         BrokerEntry {
             ts_start: self.ts_start,
             ts_end: self.ts_end,
@@ -49,10 +61,127 @@ impl BrokerRawEntry {
             data_type: self.data_type,
             url: self.url,
             size: self.rough_size,
+            rough_size: self.rough_size,
+            exact_size: self.exact_size,
         }
     }
 }
 
+/// Canonical data type, `rib` or `update`, given by name or alias, `None` if unknown.
+fn normalize_data_type(data_type: &str) -> Option<&'static str> {
+    match data_type.trim().to_lowercase().as_str() {
+        "update" | "updates" | "u" => Some("update"),
+        "rib" | "ribs" | "r" => Some("rib"),
+        _ => None,
+    }
+}
+
+/// Parse the `data_type` query parameter into `rib` or `update`, rejecting unknown data types.
+pub(crate) fn parse_data_type(
+    data_type: &Option<String>,
+) -> Result<Option<&'static str>, ApiError> {
+    match data_type {
+        None => Ok(None),
+        Some(d) => match normalize_data_type(d) {
+            Some(data_type) => Ok(Some(data_type)),
+            None => Err(ApiError::new_bad_request(format!(
+                "unknown data type: {}, valid values are `rib` and `update`",
+                d.trim()
+            ))),
+        },
+    }
+}
+
+/// Parse a timestamp query parameter, either a unix timestamp or a date time string, e.g.
+/// `2023-01-01T00:00:00`.
+pub(crate) fn parse_broker_ts(ts: &str) -> Result<NaiveDateTime, ApiError> {
+    let parsed = match ts.parse::<i64>() {
+        // it's unix timestamp
+        Ok(ts) => DateTime::from_timestamp(ts, 0).map(|t| t.naive_utc()),
+        Err(_) => NaiveDateTime::from_str(ts).ok(),
+    };
+    parsed.ok_or_else(|| ApiError::new_bad_request(format!("cannot parse time string: {}", ts)))
+}
+
+/// Filters of a query on the `items` table of MRT files.
+#[derive(Debug, Default)]
+pub(crate) struct ItemsFilter<'a> {
+    /// files ending at or after this time
+    pub ts_start: Option<NaiveDateTime>,
+
+    /// files starting at or before this time
+    pub ts_end: Option<NaiveDateTime>,
+
+    /// canonical project name
+    pub project: Option<&'static str>,
+
+    /// collector IDs, no filter if empty
+    pub collectors: Vec<&'a str>,
+
+    /// canonical data type, `rib` or `update`
+    pub data_type: Option<&'static str>,
+
+    /// minimum approximate file size in bytes
+    pub min_size: Option<u64>,
+
+    /// maximum approximate file size in bytes
+    pub max_size: Option<u64>,
+}
+
+/// Build a query selecting `columns` from the `items` table with the given filters, without
+/// ordering or range.
+pub(crate) fn items_query(db: &BgpkitDatabase, columns: &str, filter: &ItemsFilter) -> Builder {
+    let mut db_query = db.client.from("items").select(columns);
+
+    if let Some(ts_end) = filter.ts_end {
+        let ts_str = ts_end.format("%Y-%m-%dT%X").to_string();
+        db_query = db_query.lte("ts_start", ts_str);
+    }
+
+    if let Some(ts_start) = filter.ts_start {
+        let ts_str = ts_start.format("%Y-%m-%dT%X").to_string();
+        db_query = db_query.gte("ts_end", ts_str);
+    }
+
+    if let Some(project) = filter.project {
+        db_query = db_query.or(collector_registry().collector_filter(project));
+    }
+
+    if !filter.collectors.is_empty() {
+        db_query = db_query.in_("collector_id", &filter.collectors);
+    }
+
+    if let Some(data_type) = filter.data_type {
+        db_query = db_query.eq("data_type", data_type);
+    }
+
+    if let Some(min_size) = filter.min_size {
+        db_query = db_query.gte("rough_size", min_size.to_string());
+    }
+
+    if let Some(max_size) = filter.max_size {
+        db_query = db_query.lte("rough_size", max_size.to_string());
+    }
+
+    db_query
+}
+
+/// Total order of MRT files, for paging through all files matching a query.
+pub(crate) const ITEMS_ORDER: &str = "ts_start.asc,collector_id.asc,data_type.asc,url.asc";
+
+/// maximum number of files matching a query for which totals are computed
+const MAX_TOTALS_FILES: usize = 100_000;
+
+/// number of rows fetched per request when summing file sizes
+const TOTALS_PAGE_SIZE: usize = 10_000;
+
+/// Sizes of a file, summed for the totals of a query.
+#[derive(Deserialize, Debug)]
+struct ItemSizes {
+    rough_size: u64,
+    exact_size: u64,
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct BrokerResponse {
     page: usize,
@@ -61,6 +190,17 @@ pub struct BrokerResponse {
     /// count of items returned in current query
     count: usize,
 
+    /// number of files matching the query across all pages, only set with `totals=true`
+    total_files: Option<u64>,
+
+    /// total approximate size in bytes of the files matching the query across all pages, only set
+    /// with `totals=true`
+    total_rough_size: Option<u64>,
+
+    /// total exact size in bytes of the files matching the query across all pages, files with
+    /// unknown exact size are not counted. only set with `totals=true`
+    total_exact_size: Option<u64>,
+
     data: Vec<BrokerEntry>,
 }
 
@@ -72,12 +212,24 @@ pub struct BrokerSearchQuery {
     /// duration before `ts_end` or after `ts_start`
     duration: Option<String>,
 
-    /// filter by route collector projects, i.e. `route-views` or `riperis`
+    /// filter by route collector project, e.g. `route-views`, `riperis`, `pch` or `isolario`
     project: Option<String>,
 
     /// filter by collector IDs, e.g. 'rrc00', 'route-views2. use comma to separate multiple collectors
     collectors: Option<String>,
+
+    /// filter by data type, `rib` or `update`
     data_type: Option<String>,
+
+    /// minimum approximate file size in bytes
+    min_size: Option<u64>,
+
+    /// maximum approximate file size in bytes
+    max_size: Option<u64>,
+
+    /// `true` to also return the number and total size of the files matching the query across all
+    /// pages, for queries matching at most 100,000 files
+    totals: Option<bool>,
 }
 
 /// Search for information regarding autonomous systems.
@@ -91,6 +243,7 @@ pub struct BrokerSearchQuery {
     path = "/broker",
     responses(
         (status = 200, description = "public MRT files found", body = BrokerResponse),
+        (status = 400, description = "invalid query parameters or too many files for totals"),
     ),
     params(
         BrokerSearchQuery,
@@ -99,49 +252,21 @@ pub struct BrokerSearchQuery {
 )]
 pub async fn search_broker(
     Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(latest_cache): Extension<Arc<TtlCache<BrokerLatestSet>>>,
     query: Query<BrokerSearchQuery>,
     pagination: Query<Pagination>,
 ) -> Result<Json<BrokerResponse>, ApiError> {
-    let mut db_query = db.client.from("items").select("*");
-
     //////////////////
     // TIME FILTERS //
     //////////////////
-    let mut ts_start = None;
-    let mut ts_end = None;
-    if let Some(ts_end_str) = &query.ts_end {
-        ts_end = if let Ok(ts_end) = ts_end_str.parse::<i64>() {
-            // it's unix timestamp
-            Some(NaiveDateTime::from_timestamp(ts_end, 0))
-        } else {
-            match NaiveDateTime::from_str(ts_end_str) {
-                Ok(t) => Some(t),
-                Err(_) => {
-                    return Err(ApiError::new_bad_request(format!(
-                        "cannot parse time string: {}",
-                        ts_end_str
-                    )))
-                }
-            }
-        };
-    }
-
-    if let Some(ts_start_str) = &query.ts_start {
-        ts_start = if let Ok(ts_start) = ts_start_str.parse::<i64>() {
-            // it's unix timestamp
-            Some(NaiveDateTime::from_timestamp(ts_start, 0))
-        } else {
-            match NaiveDateTime::from_str(ts_start_str) {
-                Ok(t) => Some(t),
-                Err(_) => {
-                    return Err(ApiError::new_bad_request(format!(
-                        "cannot parse time string: {}",
-                        ts_start_str
-                    )))
-                }
-            }
-        };
-    }
+    let mut ts_start = match &query.ts_start {
+        Some(ts_start_str) => Some(parse_broker_ts(ts_start_str)?),
+        None => None,
+    };
+    let mut ts_end = match &query.ts_end {
+        Some(ts_end_str) => Some(parse_broker_ts(ts_end_str)?),
+        None => None,
+    };
 
     match (ts_start, ts_end) {
         (Some(start), None) => {
@@ -177,77 +302,124 @@ pub async fn search_broker(
         _ => {}
     };
 
-    if let Some(ts_end) = ts_end {
-        let ts_str = ts_end.format("%Y-%m-%dT%X").to_string();
-        db_query = db_query.lte("ts_start", ts_str);
-    }
-
-    if let Some(ts_start) = ts_start {
-        let ts_str = ts_start.format("%Y-%m-%dT%X").to_string();
-        db_query = db_query.gte("ts_end", ts_str);
-    }
-
-    ///////////////////////
-    // COLLECTOR FILTERS //
-    ///////////////////////
-
-    if let Some(project) = &query.project {
-        match project.as_str() {
-            "route-views" | "routeviews" | "rv" => {
-                db_query = db_query.ilike("collector_id", "route-views%");
-            }
-            "ripe" | "ripencc" | "riperis" | "ris" => {
-                db_query = db_query.ilike("collector_id", "rrc%");
-            }
-            _ => {
-                // TODO: handle unrecognized cases
-            }
-        }
-    }
-
-    if let Some(collectors_str) = &query.collectors {
-        let collectors: Vec<&str> = collectors_str.split(",").map(|c| c.trim()).collect();
-        info!("{:?}", &collectors);
-        db_query = db_query.in_("collector_id", collectors);
-    }
-
-    ////////////
-    // OTHERS //
-    ////////////
-
-    if let Some(data_type) = &query.data_type {
-        match data_type.to_lowercase().as_str() {
-            "update" | "updates" | "u" => {
-                db_query = db_query.eq("data_type", "update");
-            }
-            "rib" | "ribs" | "r" => {
-                db_query = db_query.eq("data_type", "rib");
-            }
-            _ => {}
+    if let (Some(min_size), Some(max_size)) = (query.min_size, query.max_size) {
+        if min_size > max_size {
+            return Err(ApiError::new_bad_request(
+                "`min_size` must not be greater than `max_size`",
+            ));
         }
     }
+    let collectors = match &query.collectors {
+        Some(collectors) => parse_collectors(&db, &latest_cache, collectors).await?,
+        None => vec![],
+    };
+    let filter = ItemsFilter {
+        ts_start,
+        ts_end,
+        project: parse_project(&query.project)?,
+        collectors: collectors.iter().map(|c| c.as_str()).collect(),
+        data_type: parse_data_type(&query.data_type)?,
+        min_size: query.min_size,
+        max_size: query.max_size,
+    };
+    info!("{:?}", &filter);
+    let mut db_query = items_query(&db, "*", &filter);
 
-    db_query = db_query.order("ts_start.asc");
+    db_query = db_query.order(ITEMS_ORDER);
 
     let (page, page_size) = pagination.extract(1000);
     let low = page * page_size;
     let high = (page + 1) * page_size - 1;
     db_query = db_query.range(low, high);
 
-    let response = execute(db_query).await?;
+    let (response, total_files) = match query.totals.unwrap_or(false) {
+        true => match execute_with_count(db_query).await? {
+            (response, Some(total)) => (response, Some(total)),
+            (_, None) => return Err(ApiError::new_internal("database did not count the files")),
+        },
+        false => (execute(db_query).await?, None),
+    };
+
+    let data: Vec<BrokerEntry> =
+        match serde_json::from_str::<Vec<BrokerRawEntry>>(response.as_str()) {
+            Ok(entries) => entries.into_iter().map(|e| e.into_entry()).collect(),
+            Err(_) => return Err(ApiError::new_internal("cannot parse database response")),
+        };
+
+    // sum the sizes page by page, aggregate functions may be disabled in the database
+    let totals = match total_files {
+        None => None,
+        Some(files) if files > MAX_TOTALS_FILES => {
+            return Err(ApiError::new_bad_request(format!(
+                "{} files match the query, totals are limited to {} files",
+                files, MAX_TOTALS_FILES
+            )))
+        }
+        Some(files) => {
+            let sizes_query = items_query(&db, "rough_size,exact_size", &filter);
+            let sizes: Vec<ItemSizes> =
+                execute_all(sizes_query, ITEMS_ORDER, TOTALS_PAGE_SIZE).await?;
+            Some((
+                files as u64,
+                sizes.iter().map(|s| s.rough_size).sum::<u64>(),
+                sizes.iter().map(|s| s.exact_size).sum::<u64>(),
+            ))
+        }
+    };
 
-    let data: Vec<BrokerEntry> = serde_json::from_str::<Vec<BrokerRawEntry>>(response.as_str())
-        .unwrap()
-        .into_iter()
-        .map(|entry| entry.to_entry())
-        .collect();
     let count = data.len();
     let response = BrokerResponse {
         page,
         page_size,
-        data,
         count,
+        total_files: totals.map(|(files, _, _)| files),
+        total_rough_size: totals.map(|(_, rough_size, _)| rough_size),
+        total_exact_size: totals.map(|(_, _, exact_size)| exact_size),
+        data,
     };
 
     Ok(Json(response))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_data_type() {
+        assert_eq!(parse_data_type(&None).unwrap(), None);
+        assert_eq!(
+            parse_data_type(&Some(" Updates".to_string())).unwrap(),
+            Some("update")
+        );
+        assert_eq!(
+            parse_data_type(&Some("r".to_string())).unwrap(),
+            Some("rib")
+        );
+        let err = parse_data_type(&Some("bview".to_string())).unwrap_err();
+        assert!(serde_json::to_string(&err)
+            .unwrap()
+            .contains("`rib` and `update`"));
+    }
+
+    #[test]
+    fn test_into_entry() {
+        let raw: BrokerRawEntry = serde_json::from_str(
+            r#"{
+                "ts_start": "2023-01-01T00:00:00",
+                "ts_end": "2023-01-01T00:00:00",
+                "collector_id": "route-views.amsix",
+                "data_type": "rib",
+                "url": "http://archive.routeviews.org/route-views.amsix/bgpdata/2023.01/RIBS/rib.20230101.0000.bz2",
+                "rough_size": 5000000000,
+                "exact_size": 5000123456
+            }"#,
+        )
+        .unwrap();
+        let entry = raw.into_entry();
+        assert_eq!(entry.project, "route-views");
+        assert_eq!(entry.size, 5_000_000_000);
+        assert_eq!(entry.rough_size, 5_000_000_000);
+        assert_eq!(entry.exact_size, 5_000_123_456);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::api::{
+    collector_registry, expected_cadence, parse_collectors, parse_data_type, parse_project,
+    ApiError, BrokerEntry, BrokerRawEntry,
+};
+use crate::cache::TtlCache;
+use crate::db::{execute, require_feature, BgpkitDatabase, DbFeature};
+use axum::extract::Query;
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BrokerLatestEntry {
+    #[serde(flatten)]
+    file: BrokerEntry,
+
+    /// expected interval between two files of this collector and data type, in minutes
+    cadence_minutes: Option<u32>,
+
+    /// minutes since the next file was expected to be available, `0` if the collector is on time.
+    /// `null` if the cadence is unknown or the project only has historical archives
+    delay_minutes: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BrokerLatestResponse {
+    count: usize,
+    data: Vec<BrokerLatestEntry>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct BrokerLatestQuery {
+    /// filter by route collector project, e.g. `route-views`, `riperis`, `pch` or `isolario`
+    project: Option<String>,
+
+    /// filter by collector IDs, e.g. 'rrc00', 'route-views2. use comma to separate multiple collectors
+    collectors: Option<String>,
+
+    /// filter by data type, `rib` or `update`
+    data_type: Option<String>,
+}
+
+/// The most recent MRT file of each collector and data type.
+pub struct BrokerLatestSet {
+    files: Vec<BrokerEntry>,
+}
+
+impl BrokerLatestSet {
+    pub(crate) fn files(&self) -> &[BrokerEntry] {
+        &self.files
+    }
+}
+
+/// Minutes the next file after `file` is overdue at `now`, `0` if not yet expected.
+///
+/// The file following the latest one is expected one cadence after the end of the latest file, for
+/// update files covering a period as well as for RIB dumps taken at a single time.
+fn file_delay(file: &BrokerEntry, cadence: u32, now: NaiveDateTime) -> Option<i64> {
+    let ts_end = NaiveDateTime::from_str(file.ts_end.as_str()).ok()?;
+    let expected = ts_end + Duration::minutes(cadence as i64);
+    Some((now - expected).num_minutes().max(0))
+}
+
+/// Load the most recent file of each collector and data type, caching it on first use.
+pub(crate) async fn load_latest_set(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<BrokerLatestSet>,
+) -> Result<Arc<BrokerLatestSet>, ApiError> {
+    require_feature(DbFeature::ItemsLatest)?;
+    cache
+        .get_or_try_insert_with("latest", || async {
+            let db_query = db
+                .client
+                .from("items_latest")
+                .select("*")
+                .order("collector_id.asc,data_type.asc");
+            let response = execute(db_query).await?;
+            let files: Vec<BrokerEntry> =
+                match serde_json::from_str::<Vec<BrokerRawEntry>>(response.as_str()) {
+                    Ok(entries) => entries.into_iter().map(|e| e.into_entry()).collect(),
+                    Err(_) => return Err(ApiError::new_internal("cannot parse database response")),
+                };
+            Ok(BrokerLatestSet { files })
+        })
+        .await
+}
+
+/// Latest MRT files of each collector.
+///
+/// Returns the most recent RIB and update file of every collector, along with how many minutes the
+/// next file is overdue relative to the expected cadence of the collector.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/broker/latest",
+    responses(
+        (status = 200, description = "latest MRT files", body = BrokerLatestResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        BrokerLatestQuery,
+    )
+)]
+pub async fn search_broker_latest(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<BrokerLatestSet>>>,
+    query: Query<BrokerLatestQuery>,
+) -> Result<Json<BrokerLatestResponse>, ApiError> {
+    let project = parse_project(&query.project)?;
+    let data_type = parse_data_type(&query.data_type)?;
+    let collectors = match &query.collectors {
+        Some(collectors) => parse_collectors(&db, &cache, collectors).await?,
+        None => vec![],
+    };
+
+    let set = load_latest_set(&db, &cache).await?;
+    let now = Utc::now().naive_utc();
+    let data: Vec<BrokerLatestEntry> = set
+        .files
+        .iter()
+        .filter(|file| {
+            project.is_none_or(|p| file.project == p)
+                && data_type.is_none_or(|d| file.data_type == d)
+                && (collectors.is_empty() || collectors.contains(&file.collector))
+        })
+        .map(|file| {
+            let cadence = expected_cadence(file.project.as_str(), file.data_type.as_str());
+            // archived projects publish no new files, so they cannot be late
+            let historical = collector_registry()
+                .project(file.project.as_str())
+                .is_some_and(|p| p.historical);
+            BrokerLatestEntry {
+                file: file.clone(),
+                cadence_minutes: cadence,
+                delay_minutes: match historical {
+                    true => None,
+                    false => cadence.and_then(|c| file_delay(file, c, now)),
+                },
+            }
+        })
+        .collect();
+
+    Ok(Json(BrokerLatestResponse {
+        count: data.len(),
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_delay() {
+        let file = BrokerEntry {
+            ts_start: "2023-01-01T00:00:00".to_string(),
+            ts_end: "2023-01-01T00:05:00".to_string(),
+            project: "riperis".to_string(),
+            collector: "rrc00".to_string(),
+            data_type: "update".to_string(),
+            url: "https://data.ris.ripe.net/rrc00/2023.01/updates.20230101.0000.gz".to_string(),
+            size: 1024,
+            rough_size: 1024,
+            exact_size: 1024,
+        };
+        let cadence = expected_cadence("riperis", "update").unwrap();
+        let at = |ts: &str| NaiveDateTime::from_str(ts).unwrap();
+        assert_eq!(
+            file_delay(&file, cadence, at("2023-01-01T00:08:00")),
+            Some(0)
+        );
+        assert_eq!(
+            file_delay(&file, cadence, at("2023-01-01T00:40:00")),
+            Some(30)
+        );
+
+        // RIB dumps end when they start, the next one is due a cadence later
+        let rib = BrokerEntry {
+            ts_end: file.ts_start.clone(),
+            data_type: "rib".to_string(),
+            ..file
+        };
+        let cadence = expected_cadence("riperis", "rib").unwrap();
+        let due = NaiveDateTime::from_str("2023-01-01T00:00:00").unwrap()
+            + Duration::minutes(cadence as i64);
+        assert_eq!(file_delay(&rib, cadence, due), Some(0));
+        assert_eq!(
+            file_delay(&rib, cadence, due + Duration::minutes(90)),
+            Some(90)
+        );
+        assert_eq!(expected_cadence("pch", "rib"), None);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::api::{
+    items_query, parse_broker_ts, parse_collectors, ApiError, BrokerEntry, BrokerLatestSet,
+    BrokerRawEntry, ItemsFilter, ITEMS_ORDER,
+};
+use crate::cache::TtlCache;
+use crate::db::{execute_all, BgpkitDatabase};
+use axum::extract::Query;
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// how far from the requested time RIB dumps are searched, in hours
+const SNAPSHOT_RIB_WINDOW_HOURS: i64 = 24;
+
+/// number of files fetched per request when collecting snapshot files
+const SNAPSHOT_PAGE_SIZE: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BrokerSnapshot {
+    collector: String,
+
+    /// RIB dump selected for the requested time
+    rib: BrokerEntry,
+
+    /// update files between the RIB dump and the requested time, in order. empty if the RIB dump is
+    /// after the requested time
+    updates: Vec<BrokerEntry>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BrokerSnapshotResponse {
+    /// requested time
+    ts: String,
+    direction: String,
+
+    /// number of collectors with a RIB dump
+    count: usize,
+    data: Vec<BrokerSnapshot>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct BrokerSnapshotQuery {
+    /// time to reconstruct the routing state at, unix timestamp or date time string, e.g.
+    /// `2023-01-01T00:00:00`
+    ts: String,
+
+    /// filter by collector IDs, e.g. 'rrc00', 'route-views2. use comma to separate multiple
+    /// collectors. defaults to all collectors
+    collectors: Option<String>,
+
+    /// `before` (default) for the last RIB dump at or before `ts`, `after` for the first RIB dump at
+    /// or after `ts`, `nearest` for the closest one
+    direction: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SnapshotDirection {
+    Before,
+    After,
+    Nearest,
+}
+
+impl SnapshotDirection {
+    fn parse(direction: &Option<String>) -> Result<Self, ApiError> {
+        match direction.as_deref().map(|d| d.trim().to_lowercase()) {
+            None => Ok(SnapshotDirection::Before),
+            Some(d) => match d.as_str() {
+                "before" => Ok(SnapshotDirection::Before),
+                "after" => Ok(SnapshotDirection::After),
+                "nearest" => Ok(SnapshotDirection::Nearest),
+                _ => Err(ApiError::new_bad_request(format!(
+                    "unknown direction: {}, valid values are `before`, `after` and `nearest`",
+                    d
+                ))),
+            },
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SnapshotDirection::Before => "before",
+            SnapshotDirection::After => "after",
+            SnapshotDirection::Nearest => "nearest",
+        }
+    }
+}
+
+fn file_start(file: &BrokerEntry) -> Option<NaiveDateTime> {
+    NaiveDateTime::from_str(file.ts_start.as_str()).ok()
+}
+
+fn file_end(file: &BrokerEntry) -> Option<NaiveDateTime> {
+    NaiveDateTime::from_str(file.ts_end.as_str()).ok()
+}
+
+/// Select the RIB dump of each collector closest to `ts` in the given direction.
+///
+/// Ties are broken by the earlier dump, then by URL, regardless of the order of `ribs`.
+fn select_ribs(
+    ribs: Vec<BrokerEntry>,
+    ts: NaiveDateTime,
+    direction: SnapshotDirection,
+) -> BTreeMap<String, BrokerEntry> {
+    let mut selected: BTreeMap<String, ((i64, NaiveDateTime), BrokerEntry)> = BTreeMap::new();
+    for rib in ribs {
+        let start = match file_start(&rib) {
+            Some(start) => start,
+            None => continue,
+        };
+        let offset = (start - ts).num_seconds();
+        let distance = match direction {
+            SnapshotDirection::Before if offset > 0 => continue,
+            SnapshotDirection::After if offset < 0 => continue,
+            _ => offset.abs(),
+        };
+        let rank = (distance, start);
+        match selected.get(&rib.collector) {
+            Some((best, best_rib)) if (*best, &best_rib.url) <= (rank, &rib.url) => {}
+            _ => {
+                selected.insert(rib.collector.clone(), (rank, rib));
+            }
+        }
+    }
+    selected
+        .into_iter()
+        .map(|(collector, (_, rib))| (collector, rib))
+        .collect()
+}
+
+/// Update files of a collector needed to roll forward from a RIB dump to `ts`, in order.
+fn bridging_updates(
+    rib: &BrokerEntry,
+    updates: &[BrokerEntry],
+    ts: NaiveDateTime,
+) -> Vec<BrokerEntry> {
+    let rib_ts = match file_start(rib) {
+        Some(rib_ts) if rib_ts < ts => rib_ts,
+        _ => return vec![],
+    };
+    let mut bridging: Vec<BrokerEntry> = updates
+        .iter()
+        .filter(|update| {
+            update.collector == rib.collector
+                && file_end(update).is_some_and(|end| end > rib_ts)
+                && file_start(update).is_some_and(|start| start <= ts)
+        })
+        .cloned()
+        .collect();
+    bridging.sort_by(|a, b| a.ts_start.cmp(&b.ts_start));
+    bridging
+}
+
+async fn fetch_items(
+    db: &Arc<BgpkitDatabase>,
+    filter: &ItemsFilter<'_>,
+) -> Result<Vec<BrokerEntry>, ApiError> {
+    let db_query = items_query(db, "*", filter);
+    let entries: Vec<BrokerRawEntry> =
+        execute_all(db_query, ITEMS_ORDER, SNAPSHOT_PAGE_SIZE).await?;
+    Ok(entries.into_iter().map(|e| e.into_entry()).collect())
+}
+
+/// RIB snapshot closest to a time.
+///
+/// Returns one RIB dump per collector, selected before, after or nearest to the requested time
+/// within 24 hours, along with the ordered update files needed to roll the RIB forward to that time.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/broker/snapshot",
+    responses(
+        (status = 200, description = "RIB dumps and bridging update files", body = BrokerSnapshotResponse),
+        (status = 400, description = "invalid query parameters"),
+    ),
+    params(
+        BrokerSnapshotQuery,
+    )
+)]
+pub async fn search_broker_snapshot(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(latest_cache): Extension<Arc<TtlCache<BrokerLatestSet>>>,
+    query: Query<BrokerSnapshotQuery>,
+) -> Result<Json<BrokerSnapshotResponse>, ApiError> {
+    let ts = parse_broker_ts(query.ts.as_str())?;
+    let direction = SnapshotDirection::parse(&query.direction)?;
+    let collectors = match &query.collectors {
+        Some(collectors) => parse_collectors(&db, &latest_cache, collectors).await?,
+        None => vec![],
+    };
+
+    let window = Duration::hours(SNAPSHOT_RIB_WINDOW_HOURS);
+    let rib_filter = ItemsFilter {
+        ts_start: Some(match direction {
+            SnapshotDirection::After => ts,
+            _ => ts - window,
+        }),
+        ts_end: Some(match direction {
+            SnapshotDirection::Before => ts,
+            _ => ts + window,
+        }),
+        collectors: collectors.iter().map(|c| c.as_str()).collect(),
+        data_type: Some("rib"),
+        ..Default::default()
+    };
+    let ribs = select_ribs(fetch_items(&db, &rib_filter).await?, ts, direction);
+
+    // updates of the collectors whose RIB dump is before the requested time
+    let earliest_rib = ribs
+        .values()
+        .filter_map(file_start)
+        .filter(|rib_ts| *rib_ts < ts)
+        .min();
+    let updates = match earliest_rib {
+        None => vec![],
+        Some(earliest_rib) => {
+            let update_filter = ItemsFilter {
+                ts_start: Some(earliest_rib),
+                ts_end: Some(ts),
+                collectors: ribs.keys().map(|c| c.as_str()).collect(),
+                data_type: Some("update"),
+                ..Default::default()
+            };
+            fetch_items(&db, &update_filter).await?
+        }
+    };
+
+    let data: Vec<BrokerSnapshot> = ribs
+        .into_iter()
+        .map(|(collector, rib)| BrokerSnapshot {
+            updates: bridging_updates(&rib, &updates, ts),
+            collector,
+            rib,
+        })
+        .collect();
+    Ok(Json(BrokerSnapshotResponse {
+        ts: ts.format("%Y-%m-%dT%X").to_string(),
+        direction: direction.name().to_string(),
+        count: data.len(),
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(collector: &str, data_type: &str, ts_start: &str, ts_end: &str) -> BrokerEntry {
+        BrokerEntry {
+            ts_start: ts_start.to_string(),
+            ts_end: ts_end.to_string(),
+            project: "riperis".to_string(),
+            collector: collector.to_string(),
+            data_type: data_type.to_string(),
+            url: format!("https://data.ris.ripe.net/{}/{}", collector, ts_start),
+            size: 1024,
+            rough_size: 1024,
+            exact_size: 1024,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_selection() {
+        let ts = NaiveDateTime::from_str("2023-01-01T09:00:00").unwrap();
+        let ribs = vec![
+            file("rrc00", "rib", "2023-01-01T00:00:00", "2023-01-01T00:00:00"),
+            file("rrc00", "rib", "2023-01-01T08:00:00", "2023-01-01T08:00:00"),
+            file("rrc00", "rib", "2023-01-01T16:00:00", "2023-01-01T16:00:00"),
+            file("rrc01", "rib", "2023-01-01T16:00:00", "2023-01-01T16:00:00"),
+        ];
+
+        let before = select_ribs(ribs.clone(), ts, SnapshotDirection::Before);
+        assert_eq!(before.len(), 1);
+        assert_eq!(before["rrc00"].ts_start, "2023-01-01T08:00:00");
+        let after = select_ribs(ribs.clone(), ts, SnapshotDirection::After);
+        assert_eq!(after["rrc00"].ts_start, "2023-01-01T16:00:00");
+        assert_eq!(after.len(), 2);
+        let nearest = select_ribs(ribs, ts, SnapshotDirection::Nearest);
+        assert_eq!(nearest["rrc00"].ts_start, "2023-01-01T08:00:00");
+
+        let updates = vec![
+            file(
+                "rrc00",
+                "update",
+                "2023-01-01T08:30:00",
+                "2023-01-01T08:35:00",
+            ),
+            file(
+                "rrc00",
+                "update",
+                "2023-01-01T07:55:00",
+                "2023-01-01T08:00:00",
+            ),
+            file(
+                "rrc00",
+                "update",
+                "2023-01-01T08:00:00",
+                "2023-01-01T08:05:00",
+            ),
+            file(
+                "rrc00",
+                "update",
+                "2023-01-01T09:00:00",
+                "2023-01-01T09:05:00",
+            ),
+            file(
+                "rrc00",
+                "update",
+                "2023-01-01T09:05:00",
+                "2023-01-01T09:10:00",
+            ),
+        ];
+        let bridging = bridging_updates(&before["rrc00"], &updates, ts);
+        let starts: Vec<&str> = bridging.iter().map(|u| u.ts_start.as_str()).collect();
+        assert_eq!(
+            starts,
+            vec![
+                "2023-01-01T08:00:00",
+                "2023-01-01T08:30:00",
+                "2023-01-01T09:00:00"
+            ]
+        );
+        assert!(bridging_updates(&after["rrc00"], &updates, ts).is_empty());
+    }
+
+    #[test]
+    fn test_select_ribs_ties() {
+        let ts = NaiveDateTime::from_str("2023-01-01T12:00:00").unwrap();
+        let ribs = vec![
+            file("rrc00", "rib", "2023-01-01T16:00:00", "2023-01-01T16:00:00"),
+            file("rrc00", "rib", "2023-01-01T08:00:00", "2023-01-01T08:00:00"),
+        ];
+        let mut reversed = ribs.clone();
+        reversed.reverse();
+
+        // equally distant dumps before and after: the earlier one wins in any input order
+        for ribs in [ribs, reversed] {
+            let nearest = select_ribs(ribs, ts, SnapshotDirection::Nearest);
+            assert_eq!(nearest["rrc00"].ts_start, "2023-01-01T08:00:00");
+        }
+
+        // duplicate dumps of the same time are broken by URL
+        let mut mirror = file("rrc00", "rib", "2023-01-01T08:00:00", "2023-01-01T08:00:00");
+        mirror.url = "https://mirror.example.com/rrc00/bview.gz".to_string();
+        let original = file("rrc00", "rib", "2023-01-01T08:00:00", "2023-01-01T08:00:00");
+        for ribs in [
+            vec![mirror.clone(), original.clone()],
+            vec![original.clone(), mirror.clone()],
+        ] {
+            let before = select_ribs(ribs, ts, SnapshotDirection::Before);
+            assert_eq!(before["rrc00"].url, original.url);
+        }
+
+        // dumps exactly at `ts` count in both directions, unparsable times are skipped
+        let ribs = vec![
+            file("rrc00", "rib", "2023-01-01T12:00:00", "2023-01-01T12:00:00"),
+            file("rrc01", "rib", "not a time", "not a time"),
+        ];
+        let before = select_ribs(ribs.clone(), ts, SnapshotDirection::Before);
+        let after = select_ribs(ribs, ts, SnapshotDirection::After);
+        assert_eq!(before.len(), 1);
+        assert_eq!(before["rrc00"].ts_start, after["rrc00"].ts_start);
+        assert!(select_ribs(vec![], ts, SnapshotDirection::Nearest).is_empty());
+    }
+}
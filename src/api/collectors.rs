@@ -0,0 +1,307 @@
+use crate::api::{collector_registry, load_latest_set, parse_project, ApiError, BrokerLatestSet};
+use crate::cache::TtlCache;
+use crate::db::{execute, execute_all, require_feature, BgpkitDatabase, DbFeature};
+use axum::extract::Query;
+use axum::{Extension, Json};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// number of concurrent requests when looking up the first file of each collector
+const FIRST_FILE_CONCURRENCY: usize = 8;
+
+/// number of rows fetched per request when counting peers
+const PEERS_PAGE_SIZE: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CollectorInfo {
+    /// collector ID, as used in broker filters and results
+    id: String,
+
+    /// route collector project, e.g. `route-views` or `riperis`
+    project: String,
+
+    /// city and country code of the collector, `null` for collectors not in the catalog
+    location: Option<String>,
+
+    /// Internet exchange point the collector peers at, `null` for multihop collectors and
+    /// collectors not in the catalog
+    ixp: Option<String>,
+
+    /// address families collected, `ipv4` and/or `ipv6`. empty for collectors not in the catalog
+    families: Vec<String>,
+
+    /// the project of the collector no longer publishes new files
+    historical: bool,
+
+    /// expected interval between two RIB dumps, in minutes
+    rib_cadence_minutes: Option<u32>,
+
+    /// expected interval between two update files, in minutes
+    update_cadence_minutes: Option<u32>,
+
+    /// start time of the first file of the collector
+    first_file: Option<String>,
+
+    /// end time of the most recent file of the collector
+    last_file: Option<String>,
+
+    /// number of peers in the latest peer statistics
+    peers: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollectorsResponse {
+    count: usize,
+    data: Vec<CollectorInfo>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct CollectorsQuery {
+    /// filter by route collector project, e.g. `route-views`, `riperis`, `pch` or `isolario`
+    project: Option<String>,
+}
+
+/// First file time and peer count of each collector.
+pub struct CollectorsSummary {
+    first_files: HashMap<String, String>,
+    peers: HashMap<String, usize>,
+}
+
+#[derive(Deserialize)]
+struct ItemTime {
+    ts_start: String,
+}
+
+#[derive(Deserialize)]
+struct PeerCollector {
+    collector: String,
+}
+
+/// Expected interval between two files of a project and data type, in minutes.
+pub(crate) fn expected_cadence(project: &str, data_type: &str) -> Option<u32> {
+    let project = collector_registry().project(project)?;
+    match data_type {
+        "rib" => project.rib_cadence_minutes,
+        "update" => project.update_cadence_minutes,
+        _ => None,
+    }
+}
+
+/// Parse a comma-separated list of collector IDs into lowercase IDs without duplicates.
+fn parse_collector_ids(collectors: &str) -> Result<Vec<String>, ApiError> {
+    let mut ids = vec![];
+    for c in collectors.split(',').filter(|c| !c.trim().is_empty()) {
+        let id = c.trim().to_lowercase();
+        if !id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_'))
+        {
+            return Err(ApiError::new_bad_request(format!(
+                "invalid collector ID: {}",
+                c.trim()
+            )));
+        }
+        if !ids.contains(&id) {
+            ids.push(id)
+        }
+    }
+    Ok(ids)
+}
+
+/// Parse a comma-separated list of collector IDs, rejecting unknown collectors.
+///
+/// Collectors in the catalog or matching the collector ID prefixes of a project are accepted, e.g.
+/// newly deployed RIPE RIS or RouteViews collectors. Other collectors are accepted if they have
+/// files in the `items_latest` view.
+pub(crate) async fn parse_collectors(
+    db: &Arc<BgpkitDatabase>,
+    latest_cache: &TtlCache<BrokerLatestSet>,
+    collectors: &str,
+) -> Result<Vec<String>, ApiError> {
+    let ids = parse_collector_ids(collectors)?;
+    let registry = collector_registry();
+    let unknown: Vec<&String> = ids
+        .iter()
+        .filter(|id| registry.project_of(id).is_none())
+        .collect();
+    if unknown.is_empty() {
+        return Ok(ids);
+    }
+
+    let latest = match require_feature(DbFeature::ItemsLatest) {
+        Ok(()) => Some(load_latest_set(db, latest_cache).await?),
+        Err(_) => None,
+    };
+    for id in unknown {
+        let published = latest
+            .as_ref()
+            .is_some_and(|latest| latest.files().iter().any(|f| &f.collector == id));
+        if !published {
+            return Err(ApiError::new_bad_request(format!(
+                "unknown collector: {}, see `/collectors` for valid values",
+                id
+            )));
+        }
+    }
+    Ok(ids)
+}
+
+/// Start time of the first file of a collector, `None` if it has no files.
+async fn fetch_first_file(
+    db: Arc<BgpkitDatabase>,
+    collector: String,
+) -> Result<Option<(String, String)>, ApiError> {
+    let db_query = db
+        .client
+        .from("items")
+        .select("ts_start")
+        .eq("collector_id", collector.as_str())
+        .order("ts_start.asc")
+        .limit(1);
+    let response = execute(db_query).await?;
+    match serde_json::from_str::<Vec<ItemTime>>(response.as_str()) {
+        Ok(items) => Ok(items
+            .into_iter()
+            .next()
+            .map(|item| (collector, item.ts_start))),
+        Err(_) => Err(ApiError::new_internal("cannot parse database response")),
+    }
+}
+
+/// Load the first file time and peer count of `collectors`, caching them on first use.
+///
+/// Summaries are cached per set of collectors, so collectors publishing their first files are
+/// looked up right away.
+async fn load_collectors_summary(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<CollectorsSummary>,
+    collectors: &[&str],
+) -> Result<Arc<CollectorsSummary>, ApiError> {
+    let mut ids = collectors.to_vec();
+    ids.sort();
+    let key = ids.join(",");
+    cache
+        .get_or_try_insert_with(key.as_str(), || async {
+            let lookups: Vec<_> = collectors
+                .iter()
+                .map(|c| fetch_first_file(db.clone(), c.to_string()))
+                .collect();
+            let first_files: HashMap<String, String> = stream::iter(lookups)
+                .buffer_unordered(FIRST_FILE_CONCURRENCY)
+                .try_filter_map(|first| async move { Ok(first) })
+                .try_collect()
+                .await?;
+
+            let db_query = db.client.from("peer_stats_latest").select("collector");
+            let mut peers: HashMap<String, usize> = HashMap::new();
+            for peer in
+                execute_all::<PeerCollector>(db_query, "collector.asc,ip.asc", PEERS_PAGE_SIZE)
+                    .await?
+            {
+                *peers.entry(peer.collector).or_default() += 1;
+            }
+            Ok(CollectorsSummary { first_files, peers })
+        })
+        .await
+}
+
+/// List public route collectors.
+///
+/// Returns each route collector with published files, with its project, expected file cadence, the
+/// time range of its files and its current number of peers. The location, IXP and collected address
+/// families are included for collectors in the catalog.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/collectors",
+    responses(
+        (status = 200, description = "list of route collectors", body = CollectorsResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        CollectorsQuery,
+    )
+)]
+pub async fn list_collectors(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<CollectorsSummary>>>,
+    Extension(latest_cache): Extension<Arc<TtlCache<BrokerLatestSet>>>,
+    query: Query<CollectorsQuery>,
+) -> Result<Json<CollectorsResponse>, ApiError> {
+    let project = parse_project(&query.project)?;
+    let latest = load_latest_set(&db, &latest_cache).await?;
+
+    // project and end time of the most recent file of each collector with files
+    let mut published: BTreeMap<&str, (&str, &str)> = BTreeMap::new();
+    for file in latest.files() {
+        let (_, last) = published
+            .entry(file.collector.as_str())
+            .or_insert((file.project.as_str(), ""));
+        if file.ts_end.as_str() > *last {
+            *last = file.ts_end.as_str();
+        }
+    }
+    let ids: Vec<&str> = published.keys().cloned().collect();
+    let summary = load_collectors_summary(&db, &cache, &ids).await?;
+
+    let registry = collector_registry();
+    let data: Vec<CollectorInfo> = published
+        .into_iter()
+        .filter(|(_, (p, _))| project.is_none_or(|project| *p == project))
+        .map(|(id, (project, last_file))| {
+            let catalog = registry.collector(id);
+            CollectorInfo {
+                id: id.to_string(),
+                project: project.to_string(),
+                location: catalog
+                    .map(|c| c.location.clone())
+                    .filter(|location| !location.is_empty()),
+                ixp: catalog.and_then(|c| c.ixp.clone()),
+                families: catalog.map(|c| c.families.clone()).unwrap_or_default(),
+                historical: registry.project(project).is_some_and(|p| p.historical),
+                rib_cadence_minutes: expected_cadence(project, "rib"),
+                update_cadence_minutes: expected_cadence(project, "update"),
+                first_file: summary.first_files.get(id).cloned(),
+                last_file: Some(last_file.to_string()),
+                peers: summary.peers.get(id).cloned().unwrap_or_default(),
+            }
+        })
+        .collect();
+    Ok(Json(CollectorsResponse {
+        count: data.len(),
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_collectors() {
+        assert_eq!(
+            parse_collector_ids("RRC00, route-views2,rrc00,").unwrap(),
+            vec!["rrc00", "route-views2"]
+        );
+        assert!(parse_collector_ids("rrc00,rrc 99").is_err());
+        assert!(parse_collector_ids("rrc00,\"rrc01\"").is_err());
+
+        // collectors outside the catalog are known from the ID prefixes of their project
+        let registry = collector_registry();
+        for id in ["rrc27", "route-views.newix", "pch-fra"] {
+            assert!(registry.project_of(id).is_some());
+        }
+        assert!(registry.project_of("my-collector").is_none());
+
+        // catalog IDs are unique
+        let collectors = &collector_registry().collectors;
+        let mut ids: Vec<&str> = collectors.iter().map(|c| c.id.as_str()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), collectors.len());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::api::{address_space, fetch_p2c_edges, fetch_pfx2as, ApiError, Pagination, Pfx2AsEntry};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::extract::{Path, Query};
+use axum::{Extension, Json};
+use chrono::NaiveDate;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ConeSize {
+    /// Autonomous system (AS) number
+    asn: u32,
+
+    /// rank of the AS by customer cone size in ASNs, starting from 1
+    rank: usize,
+
+    /// number of ASNs in the customer cone, including the AS itself
+    num_asns: usize,
+
+    /// number of unique IPv4 prefixes originated by ASes in the customer cone
+    num_prefixes_v4: usize,
+
+    /// number of unique IPv6 prefixes originated by ASes in the customer cone
+    num_prefixes_v6: usize,
+
+    /// number of IPv4 addresses covered by prefixes in the customer cone
+    num_addresses_v4: u64,
+
+    /// number of IPv6 /48 equivalents covered by prefixes in the customer cone
+    num_48s_v6: f64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ConeResponse {
+    /// date of the relationship dataset the cone is computed from
+    date: Option<String>,
+
+    /// customer cone size
+    size: ConeSize,
+
+    /// ASNs in the customer cone, only included with `members=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    members: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ConeRankingResponse {
+    /// date of the relationship dataset the cones are computed from
+    date: Option<String>,
+    page: usize,
+    page_size: usize,
+    count: usize,
+
+    /// total number of ASes in the ranking
+    total: usize,
+    data: Vec<ConeSize>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct ConeQuery {
+    /// date of the relationship and prefix-to-origin datasets, format: YYYY-MM-DD. defaults to the
+    /// latest datasets
+    date: Option<String>,
+
+    /// include the list of ASNs in the customer cone, default false
+    members: Option<bool>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct ConeRankingQuery {
+    /// date of the relationship and prefix-to-origin datasets, format: YYYY-MM-DD. defaults to the
+    /// latest datasets
+    date: Option<String>,
+
+    /// sort order of the ranking: `asns` (default), `prefixes` or `addresses`
+    sort: Option<String>,
+}
+
+/// Customer cones of all ASes in a relationship dataset.
+pub struct ConeIndex {
+    /// date of the relationship dataset
+    date: Option<String>,
+
+    /// direct customers of each provider
+    customers: HashMap<u32, Vec<u32>>,
+
+    /// prefixes originated by each AS
+    origins: HashMap<u32, Vec<IpNet>>,
+
+    /// cone sizes of all ASes, sorted by number of ASNs in descending order
+    ranking: Vec<ConeSize>,
+
+    /// position of each AS in `ranking`
+    positions: HashMap<u32, usize>,
+}
+
+impl ConeIndex {
+    /// Build customer cones from provider-to-customer links and prefix-to-origin mappings.
+    pub fn build(date: Option<String>, edges: Vec<(u32, u32)>, prefixes: Vec<Pfx2AsEntry>) -> Self {
+        let mut customers: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut asns: BTreeSet<u32> = BTreeSet::new();
+        for (provider, customer) in edges {
+            customers.entry(provider).or_default().push(customer);
+            asns.insert(provider);
+            asns.insert(customer);
+        }
+
+        let mut origins: HashMap<u32, Vec<IpNet>> = HashMap::new();
+        for entry in prefixes {
+            if let Some(net) = entry.net() {
+                origins.entry(entry.asn).or_default().push(net);
+            }
+        }
+
+        let mut index = ConeIndex {
+            date,
+            customers,
+            origins,
+            ranking: vec![],
+            positions: HashMap::new(),
+        };
+
+        let mut ranking: Vec<ConeSize> = asns.into_iter().map(|asn| index.cone_size(asn)).collect();
+        ranking.sort_by(|a, b| b.num_asns.cmp(&a.num_asns).then(a.asn.cmp(&b.asn)));
+        for (i, size) in ranking.iter_mut().enumerate() {
+            size.rank = i + 1;
+        }
+        index.positions = ranking
+            .iter()
+            .enumerate()
+            .map(|(i, size)| (size.asn, i))
+            .collect();
+        index.ranking = ranking;
+        index
+    }
+
+    /// ASNs in the customer cone of `asn`, including `asn` itself, sorted ascending
+    pub fn members(&self, asn: u32) -> Vec<u32> {
+        let mut visited: BTreeSet<u32> = BTreeSet::new();
+        let mut queue: VecDeque<u32> = VecDeque::new();
+        visited.insert(asn);
+        queue.push_back(asn);
+        while let Some(current) = queue.pop_front() {
+            if let Some(customers) = self.customers.get(&current) {
+                for customer in customers {
+                    if visited.insert(*customer) {
+                        queue.push_back(*customer);
+                    }
+                }
+            }
+        }
+        visited.into_iter().collect()
+    }
+
+    /// customer cone size of `asn`, with `rank` set to 0
+    fn cone_size(&self, asn: u32) -> ConeSize {
+        let members = self.members(asn);
+        let mut nets: Vec<IpNet> = members
+            .iter()
+            .filter_map(|member| self.origins.get(member))
+            .flatten()
+            .cloned()
+            .collect();
+        nets.sort();
+        nets.dedup();
+        let (num_addresses_v4, num_48s_v6) = address_space(nets.iter());
+        ConeSize {
+            asn,
+            rank: 0,
+            num_asns: members.len(),
+            num_prefixes_v4: nets.iter().filter(|n| matches!(n, IpNet::V4(_))).count(),
+            num_prefixes_v6: nets.iter().filter(|n| matches!(n, IpNet::V6(_))).count(),
+            num_addresses_v4,
+            num_48s_v6,
+        }
+    }
+
+    /// customer cone size of `asn`, for ASes not in the relationship dataset the cone contains only
+    /// the AS itself
+    pub fn size_of(&self, asn: u32) -> ConeSize {
+        match self.positions.get(&asn) {
+            Some(pos) => self.ranking[*pos].clone(),
+            None => self.cone_size(asn),
+        }
+    }
+}
+
+/// Load the customer cone index for a date, computing and caching it on first use.
+pub(crate) async fn load_cone_index(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<ConeIndex>,
+    date: &Option<String>,
+) -> Result<Arc<ConeIndex>, ApiError> {
+    let pfx2as_date = match date {
+        None => None,
+        Some(d) => match NaiveDate::from_str(d) {
+            Ok(d) => Some(d),
+            Err(_) => {
+                return Err(ApiError::new_bad_request(format!(
+                    "cannot parse date string: {}",
+                    d
+                )))
+            }
+        },
+    };
+    let key = pfx2as_date
+        .map(|d| d.to_string())
+        .unwrap_or_else(|| "latest".to_string());
+    cache
+        .get_or_try_insert_with(key.as_str(), || async {
+            let (rel_date, edges) = fetch_p2c_edges(db, date).await?;
+            let prefixes = fetch_pfx2as(db, pfx2as_date, None).await?;
+            match tokio::task::spawn_blocking(move || ConeIndex::build(rel_date, edges, prefixes))
+                .await
+            {
+                Ok(index) => Ok(index),
+                Err(_) => Err(ApiError::new_internal("computing customer cones failed")),
+            }
+        })
+        .await
+}
+
+/// Customer cone of an autonomous system.
+///
+/// The customer cone contains the AS itself and all ASes reachable by following
+/// provider-to-customer links downwards, computed from the AS relationship dataset. Prefix and
+/// address counts are based on the prefixes originated by ASes in the cone.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/asninfo/{asn}/cone",
+    responses(
+        (status = 200, description = "customer cone found", body = ConeResponse),
+        (status = 400, description = "invalid query parameters"),
+    ),
+    params(
+        ("asn" = u32, Path, description = "AS number to compute the customer cone for"),
+        ConeQuery,
+    )
+)]
+pub async fn search_cone(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<ConeIndex>>>,
+    Path(asn): Path<u32>,
+    query: Query<ConeQuery>,
+) -> Result<Json<ConeResponse>, ApiError> {
+    let index = load_cone_index(&db, &cache, &query.date).await?;
+    let members = match query.members.unwrap_or(false) {
+        true => Some(index.members(asn)),
+        false => None,
+    };
+    Ok(Json(ConeResponse {
+        date: index.date.clone(),
+        size: index.size_of(asn),
+        members,
+    }))
+}
+
+/// Ranking of autonomous systems by customer cone size.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/asninfo/cones",
+    responses(
+        (status = 200, description = "customer cone ranking", body = ConeRankingResponse),
+        (status = 400, description = "invalid query parameters"),
+    ),
+    params(
+        ConeRankingQuery,
+        Pagination
+    )
+)]
+pub async fn search_cone_ranking(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<ConeIndex>>>,
+    query: Query<ConeRankingQuery>,
+    pagination: Query<Pagination>,
+) -> Result<Json<ConeRankingResponse>, ApiError> {
+    let index = load_cone_index(&db, &cache, &query.date).await?;
+
+    let mut ranking: Vec<&ConeSize> = index.ranking.iter().collect();
+    match query.sort.as_deref().unwrap_or("asns") {
+        "asns" => {}
+        "prefixes" => ranking.sort_by(|a, b| {
+            (b.num_prefixes_v4 + b.num_prefixes_v6)
+                .cmp(&(a.num_prefixes_v4 + a.num_prefixes_v6))
+                .then(a.rank.cmp(&b.rank))
+        }),
+        "addresses" => ranking.sort_by(|a, b| {
+            b.num_addresses_v4
+                .cmp(&a.num_addresses_v4)
+                .then(b.num_48s_v6.total_cmp(&a.num_48s_v6))
+                .then(a.rank.cmp(&b.rank))
+        }),
+        other => {
+            return Err(ApiError::new_bad_request(format!(
+                "unknown sort order: {}, valid values are `asns`, `prefixes` and `addresses`",
+                other
+            )))
+        }
+    }
+
+    let (page, page_size) = pagination.extract(1000);
+    let data: Vec<ConeSize> = ranking
+        .into_iter()
+        .skip(page * page_size)
+        .take(page_size)
+        .cloned()
+        .collect();
+
+    Ok(Json(ConeRankingResponse {
+        date: index.date.clone(),
+        page,
+        page_size,
+        count: data.len(),
+        total: index.ranking.len(),
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cone_index() {
+        // 1 -> 2 -> 3, 1 -> 4, 4 -> 3, and a provider loop 5 <-> 6
+        let edges = vec![(1, 2), (2, 3), (1, 4), (4, 3), (5, 6), (6, 5)];
+        let prefixes = vec![
+            Pfx2AsEntry {
+                prefix: "10.0.0.0/24".to_string(),
+                asn: 3,
+            },
+            Pfx2AsEntry {
+                prefix: "10.0.0.0/25".to_string(),
+                asn: 4,
+            },
+            Pfx2AsEntry {
+                prefix: "2001:db8::/48".to_string(),
+                asn: 2,
+            },
+        ];
+        let index = ConeIndex::build(None, edges, prefixes);
+
+        assert_eq!(index.members(1), vec![1, 2, 3, 4]);
+        assert_eq!(index.members(5), vec![5, 6]);
+        assert_eq!(index.members(100), vec![100]);
+
+        let size = index.size_of(1);
+        assert_eq!(size.rank, 1);
+        assert_eq!(size.num_asns, 4);
+        assert_eq!(size.num_prefixes_v4, 2);
+        assert_eq!(size.num_prefixes_v6, 1);
+        assert_eq!(size.num_addresses_v4, 256);
+        assert_eq!(size.num_48s_v6, 1.0);
+
+        assert_eq!(index.size_of(3).num_asns, 1);
+    }
+}
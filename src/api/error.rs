@@ -1,8 +1,8 @@
 use crate::StatusCode;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
 use serde::Serialize;
 use std::fmt::{Display, Formatter};
-use axum::Json;
 use thiserror::Error;
 
 #[derive(Serialize, Debug, Error)]
@@ -19,8 +19,7 @@ impl Display for ApiError {
 
 impl ApiError {
     pub fn new(status_code: u16, err: impl ToString) -> Self {
-        let mut errors: Vec<String> = Vec::new();
-        errors.push(err.to_string());
+        let errors: Vec<String> = vec![err.to_string()];
         ApiError {
             status_code,
             errors,
@@ -28,19 +27,17 @@ impl ApiError {
     }
 
     pub fn new_internal(err: impl ToString) -> Self {
-        let mut errors: Vec<String> = Vec::new();
-        errors.push(err.to_string());
+        let errors: Vec<String> = vec![err.to_string()];
         ApiError {
             status_code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-            errors: errors,
+            errors,
         }
     }
     pub fn new_bad_request(err: impl ToString) -> Self {
-        let mut errors: Vec<String> = Vec::new();
-        errors.push(err.to_string());
+        let errors: Vec<String> = vec![err.to_string()];
         ApiError {
             status_code: StatusCode::BAD_REQUEST.as_u16(),
-            errors: errors,
+            errors,
         }
     }
 
@@ -51,10 +48,6 @@ impl ApiError {
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::from_u16(self.status_code).unwrap(),
-            Json(&self)
-        )
-            .into_response()
+        (StatusCode::from_u16(self.status_code).unwrap(), Json(&self)).into_response()
     }
 }
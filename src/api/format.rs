@@ -0,0 +1,133 @@
+use crate::api::ApiError;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Output format of list endpoints, selected with the `format` query parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// regular JSON response envelope
+    Json,
+
+    /// comma-separated values with a header line, one row per entry
+    Csv,
+
+    /// newline-delimited JSON, one object per entry
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// parse the `format` query parameter, defaulting to JSON
+    pub fn parse(format: &Option<String>) -> Result<Self, ApiError> {
+        match format.as_deref().map(|f| f.to_lowercase()) {
+            None => Ok(OutputFormat::Json),
+            Some(f) => match f.as_str() {
+                "json" => Ok(OutputFormat::Json),
+                "csv" => Ok(OutputFormat::Csv),
+                "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+                _ => Err(ApiError::new_bad_request(format!(
+                    "unknown output format: {}, valid values are `json`, `csv` and `ndjson`",
+                    f
+                ))),
+            },
+        }
+    }
+}
+
+/// Row type of CSV output, with an explicit column order.
+pub trait CsvRow: Serialize {
+    /// serialized field names of the row, in CSV column order
+    const COLUMNS: &'static [&'static str];
+}
+
+impl<T: CsvRow> CsvRow for &T {
+    const COLUMNS: &'static [&'static str] = T::COLUMNS;
+}
+
+/// quote a CSV field if needed
+fn csv_field(value: &Value) -> String {
+    let s = match value {
+        Value::Null => return "".to_string(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    match s.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s,
+    }
+}
+
+/// Render rows as CSV with a header line of the row columns.
+///
+/// Nested values such as arrays are written as JSON strings.
+pub fn to_csv<T: CsvRow>(rows: &[T]) -> String {
+    let mut lines = vec![T::COLUMNS.join(",")];
+    for value in rows.iter().filter_map(|row| serde_json::to_value(row).ok()) {
+        let fields: Vec<String> = T::COLUMNS
+            .iter()
+            .map(|key| csv_field(value.get(key).unwrap_or(&Value::Null)))
+            .collect();
+        lines.push(fields.join(","));
+    }
+    lines.push("".to_string());
+    lines.join("\n")
+}
+
+/// Render rows as newline-delimited JSON.
+pub fn to_ndjson<T: Serialize>(rows: &[T]) -> String {
+    rows.iter()
+        .filter_map(|row| serde_json::to_string(row).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Respond with rows in CSV or NDJSON format.
+pub fn rows_response<T: CsvRow>(format: OutputFormat, rows: &[T]) -> Response {
+    match format {
+        OutputFormat::Csv => ([(header::CONTENT_TYPE, "text/csv")], to_csv(rows)).into_response(),
+        _ => (
+            [(header::CONTENT_TYPE, "application/x-ndjson")],
+            to_ndjson(rows),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        prefix: String,
+        note: Option<String>,
+        asn: u32,
+    }
+
+    impl CsvRow for Row {
+        const COLUMNS: &'static [&'static str] = &["prefix", "asn", "note"];
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let rows = vec![
+            Row {
+                prefix: "1.1.1.0/24".to_string(),
+                note: None,
+                asn: 13335,
+            },
+            Row {
+                prefix: "2001:db8::/32".to_string(),
+                note: Some("a, \"b\"".to_string()),
+                asn: 64500,
+            },
+        ];
+        assert_eq!(
+            to_csv(&rows),
+            "prefix,asn,note\n1.1.1.0/24,13335,\n2001:db8::/32,64500,\"a, \"\"b\"\"\"\n"
+        );
+        assert_eq!(to_ndjson(&rows).lines().count(), 2);
+        assert_eq!(to_csv::<Row>(&[]), "prefix,asn,note\n");
+    }
+}
@@ -0,0 +1,190 @@
+//! Relevance scoring for fuzzy name search.
+//!
+//! The scoring here works on plain strings and is independent of where the candidates come from,
+//! so the same ranking applies regardless of the data backend that produced the candidates.
+
+use std::collections::HashSet;
+
+/// score for an exact (case-insensitive) match
+const SCORE_EXACT: f64 = 1.0;
+/// base score for a prefix match
+const SCORE_PREFIX: f64 = 0.8;
+/// base score for a substring match
+const SCORE_CONTAINS: f64 = 0.6;
+/// base score for a match within the allowed number of typos
+const SCORE_TYPO: f64 = 0.4;
+/// weight of trigram similarity for everything else
+const WEIGHT_TRIGRAM: f64 = 0.4;
+
+/// Normalize a string for comparison: lowercase and collapse non-alphanumeric characters into
+/// single spaces.
+pub fn normalize(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Number of typos tolerated for a query of the given length.
+pub fn max_typos(query_len: usize) -> usize {
+    match query_len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Set of trigrams for a string, padded the same way as PostgreSQL's `pg_trgm` (two spaces
+/// before and one after each word).
+fn trigrams(s: &str) -> HashSet<String> {
+    let mut set = HashSet::new();
+    for word in s.split_whitespace() {
+        let padded: Vec<char> = format!("  {} ", word).chars().collect();
+        for window in padded.windows(3) {
+            set.insert(window.iter().collect());
+        }
+    }
+    set
+}
+
+/// Trigram similarity between two strings, between 0 and 1.
+pub fn trigram_similarity(a: &str, b: &str) -> f64 {
+    let ta = trigrams(a);
+    let tb = trigrams(b);
+    if ta.is_empty() || tb.is_empty() {
+        return 0.0;
+    }
+    let shared = ta.intersection(&tb).count();
+    shared as f64 / (ta.len() + tb.len() - shared) as f64
+}
+
+/// Damerau-Levenshtein (optimal string alignment) distance between two strings.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Relevance score of `candidate` for `query`, between 0 and 1.
+///
+/// Exact matches rank first, then prefix matches, substring matches, matches within a small
+/// number of typos (against the whole name or any of its words), and finally trigram similarity.
+/// Within the prefix and substring tiers, shorter candidates rank higher.
+pub fn score(query: &str, candidate: &str) -> f64 {
+    let q = normalize(query);
+    let c = normalize(candidate);
+    if q.is_empty() || c.is_empty() {
+        return 0.0;
+    }
+    let coverage = q.len() as f64 / c.len().max(q.len()) as f64;
+
+    if q == c {
+        return SCORE_EXACT;
+    }
+    if c.starts_with(&q) {
+        return SCORE_PREFIX + 0.1 * coverage;
+    }
+    if c.contains(&q) {
+        return SCORE_CONTAINS + 0.1 * coverage;
+    }
+
+    let q_len = q.chars().count();
+    let typos = max_typos(q_len);
+    if typos > 0 {
+        // compare against the whole name, each of its words, and the beginning of each word so
+        // that a misspelled prefix (e.g. `clodflare` for `cloudflarenet`) still matches
+        let best = std::iter::once(c.as_str())
+            .chain(c.split(' '))
+            .flat_map(|token| {
+                let prefix: String = token.chars().take(q_len + 1).collect();
+                [token.to_string(), prefix]
+            })
+            .map(|token| edit_distance(&q, &token))
+            .min()
+            .unwrap_or(usize::MAX);
+        if best <= typos {
+            return SCORE_TYPO + 0.1 * (1.0 - best as f64 / (typos + 1) as f64);
+        }
+    }
+
+    WEIGHT_TRIGRAM * trigram_similarity(&q, &c)
+}
+
+/// SQL `LIKE` patterns matching strings within one edit of `query`.
+///
+/// Each pattern is meant to be used as a substring match (i.e. wrapped with wildcards), and uses
+/// `_` as the single-character wildcard. The patterns cover one substitution, one insertion and one
+/// deletion at every position of the query.
+pub fn one_edit_patterns(query: &str) -> Vec<String> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut patterns = vec![];
+    for i in 0..chars.len() {
+        let head: String = chars[..i].iter().collect();
+        let tail: String = chars[i + 1..].iter().collect();
+        let rest: String = chars[i..].iter().collect();
+        // substitution
+        patterns.push(format!("{}_{}", head, tail));
+        // deletion
+        if chars.len() > 1 {
+            patterns.push(format!("{}{}", head, tail));
+        }
+        // insertion
+        patterns.push(format!("{}_{}", head, rest));
+    }
+    patterns.sort();
+    patterns.dedup();
+    patterns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_tiers() {
+        let exact = score("cloudflare", "CLOUDFLARE");
+        let prefix = score("cloudflare", "CLOUDFLARENET");
+        let contains = score("cloudflare", "AS-CLOUDFLARE-SPECTRUM");
+        let typo = score("clodflare", "CLOUDFLARENET, US");
+        let unrelated = score("cloudflare", "GOOGLE");
+
+        assert_eq!(exact, 1.0);
+        assert!(exact > prefix);
+        assert!(prefix > contains);
+        assert!(contains > typo);
+        assert!(typo > unrelated);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("cloudflare", "cloudflare"), 0);
+        assert_eq!(edit_distance("clodflare", "cloudflare"), 1);
+        assert_eq!(edit_distance("coludflare", "cloudflare"), 1);
+        assert_eq!(edit_distance("google", "amazon"), 6);
+    }
+
+    #[test]
+    fn test_one_edit_patterns() {
+        let patterns = one_edit_patterns("abc");
+        assert!(patterns.contains(&"a_c".to_string()));
+        assert!(patterns.contains(&"ac".to_string()));
+        assert!(patterns.contains(&"ab_c".to_string()));
+    }
+}
@@ -1,14 +1,55 @@
+mod as2rel;
 mod asninfo;
+mod aspa;
+mod bogons;
 mod broker;
+mod broker_latest;
+mod broker_snapshot;
+mod collectors;
+mod cone;
 mod error;
-mod roas;
+mod format;
+mod fuzzy;
 mod peers;
+mod pfx2as;
+mod registry;
+mod roas;
+mod roas_churn;
+mod roas_diff;
+mod roas_stats;
+mod roas_timeline;
+mod rov;
+mod rov_invalids;
+mod rpki_coverage;
+mod tals;
+mod vrp;
+mod vrp_export;
 
+pub(crate) use as2rel::*;
 pub(crate) use asninfo::*;
+pub(crate) use aspa::*;
+pub(crate) use bogons::*;
 pub(crate) use broker::*;
+pub(crate) use broker_latest::*;
+pub(crate) use broker_snapshot::*;
+pub(crate) use collectors::*;
+pub(crate) use cone::*;
 pub(crate) use error::*;
-pub(crate) use roas::*;
+pub(crate) use format::*;
 pub(crate) use peers::*;
+pub(crate) use pfx2as::*;
+pub(crate) use registry::*;
+pub(crate) use roas::*;
+pub(crate) use roas_churn::*;
+pub(crate) use roas_diff::*;
+pub(crate) use roas_stats::*;
+pub(crate) use roas_timeline::*;
+pub(crate) use rov::*;
+pub(crate) use rov_invalids::*;
+pub(crate) use rpki_coverage::*;
+pub(crate) use tals::*;
+pub(crate) use vrp::*;
+pub(crate) use vrp_export::*;
 
 use serde::Deserialize;
 use utoipa::IntoParams;
@@ -25,10 +66,7 @@ pub struct Pagination {
 impl Pagination {
     pub fn extract(&self, max_page_size: usize) -> (usize, usize) {
         (
-            match self.page {
-                None => 0,
-                Some(p) => p,
-            },
+            self.page.unwrap_or_default(),
             match self.page_size {
                 None => 100,
                 Some(p) => match p > max_page_size {
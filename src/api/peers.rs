@@ -1,11 +1,11 @@
-use std::str::FromStr;
 use crate::api::{ApiError, Pagination};
 use crate::db::BgpkitDatabase;
 use axum::extract::Query;
 use axum::{Extension, Json};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use std::sync::Arc;
-use chrono::NaiveDate;
 use utoipa::{IntoParams, ToSchema};
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
@@ -64,7 +64,7 @@ pub struct PeerStatsSearchQuery {
     min_connected: Option<u32>,
 
     /// show latest information, default true
-    latest: Option<bool>
+    latest: Option<bool>,
 }
 
 /// Public route collector peers information.
@@ -85,9 +85,8 @@ pub async fn search_peer_stats(
     query: Query<PeerStatsSearchQuery>,
     pagination: Query<Pagination>,
 ) -> Result<Json<PeerStatsResponse>, ApiError> {
-
     let mut is_latest = false;
-    let table = match &query.latest{
+    let table = match &query.latest {
         None => {
             is_latest = true;
             "peer_stats_latest"
@@ -145,12 +144,8 @@ pub async fn search_peer_stats(
     }
 
     let (page, page_size) = match is_latest {
-        true => {
-            (0, 10000)
-        }
-        false => {
-            pagination.extract(1000)
-        }
+        true => (0, 10000),
+        false => pagination.extract(1000),
     };
 
     let low = page * page_size;
@@ -0,0 +1,143 @@
+use crate::api::ApiError;
+use crate::db::{execute_all, require_feature, BgpkitDatabase, DbFeature};
+use chrono::NaiveDate;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// number of rows fetched per request when loading prefix-to-origin data
+const PFX2AS_PAGE_SIZE: usize = 10_000;
+
+/// number of prefixes looked up per request, keeping request URLs reasonably short
+const PFX2AS_PREFIX_BATCH_SIZE: usize = 200;
+
+/// Prefix-to-origin mapping entry, i.e. a prefix announced in BGP and its origin AS.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pfx2AsEntry {
+    /// announced IP prefix
+    pub prefix: String,
+
+    /// origin AS number
+    pub asn: u32,
+}
+
+impl Pfx2AsEntry {
+    /// parsed prefix, `None` if the prefix string is malformed
+    pub fn net(&self) -> Option<IpNet> {
+        IpNet::from_str(self.prefix.as_str())
+            .ok()
+            .map(|n| n.trunc())
+    }
+}
+
+/// Fetch prefix-to-origin mappings for a date, optionally limited to a set of origin ASNs.
+///
+/// Without a date, the `pfx2as_latest` view is used.
+pub(crate) async fn fetch_pfx2as(
+    db: &Arc<BgpkitDatabase>,
+    date: Option<NaiveDate>,
+    asns: Option<&[u32]>,
+) -> Result<Vec<Pfx2AsEntry>, ApiError> {
+    require_feature(DbFeature::Pfx2As)?;
+    let mut db_query = match date {
+        None => db.client.from("pfx2as_latest").select("prefix,asn"),
+        Some(date) => db
+            .client
+            .from("pfx2as")
+            .select("prefix,asn")
+            .eq("date", date.to_string()),
+    };
+    if let Some(asns) = asns {
+        db_query = db_query.in_("asn", asns.iter().map(|asn| asn.to_string()));
+    }
+    execute_all(db_query, "prefix.asc,asn.asc", PFX2AS_PAGE_SIZE).await
+}
+
+/// Prefixes among `prefixes` that are announced in BGP with any origin on `date`, exact matches
+/// only.
+///
+/// Without a date, the `pfx2as_latest` view is used.
+pub(crate) async fn fetch_announced_prefixes(
+    db: &Arc<BgpkitDatabase>,
+    prefixes: &[String],
+    date: Option<NaiveDate>,
+) -> Result<HashSet<String>, ApiError> {
+    require_feature(DbFeature::Pfx2As)?;
+    let mut announced = HashSet::new();
+    for chunk in prefixes.chunks(PFX2AS_PREFIX_BATCH_SIZE) {
+        let db_query = match date {
+            None => db.client.from("pfx2as_latest").select("prefix,asn"),
+            Some(date) => db
+                .client
+                .from("pfx2as")
+                .select("prefix,asn")
+                .eq("date", date.to_string()),
+        }
+        .in_("prefix", chunk);
+        let entries: Vec<Pfx2AsEntry> =
+            execute_all(db_query, "prefix.asc,asn.asc", PFX2AS_PAGE_SIZE).await?;
+        announced.extend(entries.into_iter().map(|entry| entry.prefix));
+    }
+    Ok(announced)
+}
+
+/// Covered address space of a set of prefixes, with overlapping prefixes counted once.
+///
+/// Returns the number of IPv4 addresses and the number of IPv6 /48 equivalents.
+pub fn address_space<'a>(nets: impl IntoIterator<Item = &'a IpNet>) -> (u64, f64) {
+    let mut v4: Vec<(u128, u128)> = vec![];
+    let mut v6: Vec<(u128, u128)> = vec![];
+    for net in nets {
+        match net {
+            IpNet::V4(n) => v4.push((
+                u32::from(n.network()) as u128,
+                u32::from(n.broadcast()) as u128,
+            )),
+            IpNet::V6(n) => v6.push((u128::from(n.network()), u128::from(n.broadcast()))),
+        }
+    }
+    let v4_addresses = merged_range_size(v4);
+    let v6_addresses = merged_range_size(v6);
+    (v4_addresses as u64, v6_addresses as f64 / 2f64.powi(80))
+}
+
+/// total size of a set of inclusive ranges after merging overlaps
+fn merged_range_size(mut ranges: Vec<(u128, u128)>) -> u128 {
+    ranges.sort();
+    let mut total: u128 = 0;
+    let mut current: Option<(u128, u128)> = None;
+    for (start, end) in ranges {
+        current = match current {
+            Some((cur_start, cur_end)) if start <= cur_end.saturating_add(1) => {
+                Some((cur_start, cur_end.max(end)))
+            }
+            Some((cur_start, cur_end)) => {
+                total = total.saturating_add((cur_end - cur_start).saturating_add(1));
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((cur_start, cur_end)) = current {
+        total = total.saturating_add((cur_end - cur_start).saturating_add(1));
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_space() {
+        let nets: Vec<IpNet> = ["1.1.1.0/24", "1.1.1.0/25", "1.1.2.0/24", "2001:db8::/47"]
+            .iter()
+            .map(|s| IpNet::from_str(s).unwrap())
+            .collect();
+        let (v4, v6) = address_space(nets.iter());
+        assert_eq!(v4, 512);
+        assert_eq!(v6, 2.0);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::api::ApiError;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// A route collector project, e.g. RouteViews or RIPE RIS.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct ProjectDef {
+    /// canonical name of the project, as returned in results
+    pub name: String,
+
+    /// alternative names accepted in `project` filters, case-insensitive
+    #[serde(default)]
+    pub aliases: Vec<String>,
+
+    /// collector ID prefixes identifying collectors of the project not listed in the catalog
+    #[serde(default)]
+    pub collector_prefixes: Vec<String>,
+
+    /// expected interval between two RIB dumps, in minutes
+    pub rib_cadence_minutes: Option<u32>,
+
+    /// expected interval between two update files, in minutes
+    pub update_cadence_minutes: Option<u32>,
+
+    /// the project no longer publishes new files, only historical archives are available
+    #[serde(default)]
+    pub historical: bool,
+}
+
+/// A route collector of a project.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct CollectorDef {
+    /// collector ID, as used in the `items` table
+    pub id: String,
+
+    /// canonical name of the project of the collector
+    pub project: String,
+
+    /// city and country code of the collector
+    #[serde(default)]
+    pub location: String,
+
+    /// Internet exchange point the collector peers at, `None` for multihop collectors
+    pub ixp: Option<String>,
+
+    /// address families collected, `ipv4` and/or `ipv6`
+    #[serde(default = "default_families")]
+    pub families: Vec<String>,
+}
+
+fn default_families() -> Vec<String> {
+    vec!["ipv4".to_string(), "ipv6".to_string()]
+}
+
+/// Route collector projects and collectors known to the API.
+///
+/// The built-in registry covers RIPE RIS, RouteViews, PCH and the Isolario archives. Custom or
+/// private projects and collectors can be added from a JSON file with `projects` and `collectors`
+/// lists, see [load_custom_registry].
+#[derive(Deserialize, Debug, Clone, Default)]
+pub(crate) struct CollectorRegistry {
+    #[serde(default)]
+    pub projects: Vec<ProjectDef>,
+
+    #[serde(default)]
+    pub collectors: Vec<CollectorDef>,
+}
+
+static REGISTRY: OnceLock<CollectorRegistry> = OnceLock::new();
+
+/// RIPE RIS collectors: ID, location and IXP, including retired ones with historical files
+const RIS_COLLECTORS: &[(&str, &str, Option<&str>)] = &[
+    ("rrc00", "Amsterdam, NL", None),
+    ("rrc01", "London, GB", Some("LINX")),
+    ("rrc02", "Paris, FR", Some("SFINX")),
+    ("rrc03", "Amsterdam, NL", Some("AMS-IX")),
+    ("rrc04", "Geneva, CH", Some("CIXP")),
+    ("rrc05", "Vienna, AT", Some("VIX")),
+    ("rrc06", "Tokyo, JP", Some("DIX-IE")),
+    ("rrc07", "Stockholm, SE", Some("Netnod")),
+    ("rrc08", "San Jose, US", Some("MAE-West")),
+    ("rrc09", "Zurich, CH", Some("TIX")),
+    ("rrc10", "Milan, IT", Some("MIX")),
+    ("rrc11", "New York, US", Some("NYIIX")),
+    ("rrc12", "Frankfurt, DE", Some("DE-CIX")),
+    ("rrc13", "Moscow, RU", Some("MSK-IX")),
+    ("rrc14", "Palo Alto, US", Some("PAIX")),
+    ("rrc15", "Sao Paulo, BR", Some("PTTMetro-SP")),
+    ("rrc16", "Miami, US", Some("Equinix Miami")),
+    ("rrc18", "Barcelona, ES", Some("CATNIX")),
+    ("rrc19", "Johannesburg, ZA", Some("NAP Africa JB")),
+    ("rrc20", "Zurich, CH", Some("SwissIX")),
+    ("rrc21", "Paris, FR", Some("France-IX")),
+    ("rrc22", "Bucharest, RO", Some("InterLAN")),
+    ("rrc23", "Singapore, SG", Some("Equinix Singapore")),
+    ("rrc24", "Montevideo, UY", None),
+    ("rrc25", "Amsterdam, NL", None),
+    ("rrc26", "Dubai, AE", Some("UAE-IX")),
+];
+
+/// RouteViews collectors: ID, location and IXP
+const ROUTEVIEWS_COLLECTORS: &[(&str, &str, Option<&str>)] = &[
+    ("route-views2", "Eugene, US", None),
+    ("route-views3", "Eugene, US", None),
+    ("route-views4", "Eugene, US", None),
+    ("route-views5", "Eugene, US", None),
+    ("route-views6", "Eugene, US", None),
+    ("route-views.amsix", "Amsterdam, NL", Some("AMS-IX")),
+    ("route-views.bdix", "Dhaka, BD", Some("BDIX")),
+    ("route-views.bknix", "Bangkok, TH", Some("BKNIX")),
+    (
+        "route-views.chicago",
+        "Chicago, US",
+        Some("Equinix Chicago"),
+    ),
+    ("route-views.chile", "Santiago, CL", Some("PIT Chile")),
+    ("route-views.eqix", "Ashburn, US", Some("Equinix Ashburn")),
+    ("route-views.flix", "Miami, US", Some("FL-IX")),
+    (
+        "route-views.fortaleza",
+        "Fortaleza, BR",
+        Some("IX.br Fortaleza"),
+    ),
+    ("route-views.gixa", "Accra, GH", Some("GIXA")),
+    ("route-views.gorex", "Hagatna, GU", Some("GOREX")),
+    ("route-views.isc", "Palo Alto, US", Some("PAIX")),
+    ("route-views.jinx", "Johannesburg, ZA", Some("JINX")),
+    ("route-views.kixp", "Nairobi, KE", Some("KIXP")),
+    ("route-views.linx", "London, GB", Some("LINX")),
+    ("route-views.mwix", "Indianapolis, US", Some("MidWest-IX")),
+    (
+        "route-views.napafrica",
+        "Johannesburg, ZA",
+        Some("NAPAfrica"),
+    ),
+    ("route-views.nwax", "Portland, US", Some("NWAX")),
+    ("route-views.ny", "New York, US", Some("DE-CIX New York")),
+    ("route-views.perth", "Perth, AU", Some("WAIX")),
+    ("route-views.peru", "Lima, PE", Some("Peru IX")),
+    ("route-views.phoix", "Phoenix, US", Some("PhoIX")),
+    (
+        "route-views.rio",
+        "Rio de Janeiro, BR",
+        Some("IX.br Rio de Janeiro"),
+    ),
+    (
+        "route-views.saopaulo",
+        "Sao Paulo, BR",
+        Some("IX.br Sao Paulo"),
+    ),
+    (
+        "route-views2.saopaulo",
+        "Sao Paulo, BR",
+        Some("IX.br Sao Paulo"),
+    ),
+    ("route-views.sfmix", "San Francisco, US", Some("SFMIX")),
+    ("route-views.sg", "Singapore, SG", Some("Equinix Singapore")),
+    ("route-views.soxrs", "Belgrade, RS", Some("SOX")),
+    ("route-views.sydney", "Sydney, AU", Some("Equinix Sydney")),
+    ("route-views.telxatl", "Atlanta, US", Some("TELXATL")),
+    ("route-views.uaeix", "Dubai, AE", Some("UAE-IX")),
+    ("route-views.wide", "Tokyo, JP", Some("DIX-IE")),
+];
+
+/// Isolario multihop collectors, archived since the project ended
+const ISOLARIO_COLLECTORS: &[(&str, &str, Option<&str>)] = &[
+    ("alderaan", "Pisa, IT", None),
+    ("dagobah", "Pisa, IT", None),
+    ("korriban", "Pisa, IT", None),
+    ("naboo", "Pisa, IT", None),
+    ("taris", "Pisa, IT", None),
+];
+
+fn project(
+    name: &str,
+    aliases: &[&str],
+    collector_prefixes: &[&str],
+    cadence: (Option<u32>, Option<u32>),
+    historical: bool,
+) -> ProjectDef {
+    ProjectDef {
+        name: name.to_string(),
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        collector_prefixes: collector_prefixes.iter().map(|p| p.to_string()).collect(),
+        rib_cadence_minutes: cadence.0,
+        update_cadence_minutes: cadence.1,
+        historical,
+    }
+}
+
+fn collector_table(project: &str, table: &[(&str, &str, Option<&str>)]) -> Vec<CollectorDef> {
+    table
+        .iter()
+        .map(|(id, location, ixp)| CollectorDef {
+            id: id.to_string(),
+            project: project.to_string(),
+            location: location.to_string(),
+            ixp: ixp.map(|ixp| ixp.to_string()),
+            families: match *id {
+                "route-views2" => vec!["ipv4".to_string()],
+                "route-views6" => vec!["ipv6".to_string()],
+                _ => default_families(),
+            },
+        })
+        .collect()
+}
+
+impl CollectorRegistry {
+    /// built-in projects and collectors
+    pub fn builtin() -> Self {
+        let projects = vec![
+            project(
+                "route-views",
+                &["routeviews", "rv"],
+                &["route-views"],
+                (Some(2 * 60), Some(15)),
+                false,
+            ),
+            project(
+                "riperis",
+                &["ripe", "ripencc", "ris"],
+                &["rrc"],
+                (Some(8 * 60), Some(5)),
+                false,
+            ),
+            project(
+                "pch",
+                &["packet-clearing-house"],
+                &["pch"],
+                (None, None),
+                false,
+            ),
+            project("isolario", &[], &[], (Some(2 * 60), Some(5)), true),
+        ];
+        let mut collectors = collector_table("riperis", RIS_COLLECTORS);
+        collectors.extend(collector_table("route-views", ROUTEVIEWS_COLLECTORS));
+        collectors.extend(collector_table("isolario", ISOLARIO_COLLECTORS));
+        CollectorRegistry {
+            projects,
+            collectors,
+        }
+    }
+
+    /// Add custom projects and collectors, replacing existing ones with the same name or ID.
+    ///
+    /// Names, aliases and IDs are lowercased to match the normalized query parameters. Fails if a
+    /// collector belongs to an unknown project.
+    pub fn extend(&mut self, custom: CollectorRegistry) -> Result<(), String> {
+        for mut project in custom.projects {
+            project.name = project.name.to_lowercase();
+            project.aliases = project.aliases.iter().map(|a| a.to_lowercase()).collect();
+            self.projects.retain(|p| p.name != project.name);
+            self.projects.push(project);
+        }
+        for mut collector in custom.collectors {
+            collector.id = collector.id.to_lowercase();
+            collector.project = collector.project.to_lowercase();
+            if self.project(collector.project.as_str()).is_none() {
+                return Err(format!(
+                    "collector {} belongs to unknown project {}",
+                    collector.id, collector.project
+                ));
+            }
+            self.collectors.retain(|c| c.id != collector.id);
+            self.collectors.push(collector);
+        }
+        Ok(())
+    }
+
+    /// canonical names of all projects
+    pub fn project_names(&self) -> Vec<&str> {
+        self.projects.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    pub fn project(&self, name: &str) -> Option<&ProjectDef> {
+        self.projects.iter().find(|p| p.name == name)
+    }
+
+    /// Canonical name of a project given by name or alias, `None` if unknown.
+    pub fn normalize_project(&self, project: &str) -> Option<&str> {
+        let project = project.trim().to_lowercase();
+        self.projects
+            .iter()
+            .find(|p| p.name == project || p.aliases.contains(&project))
+            .map(|p| p.name.as_str())
+    }
+
+    pub fn collector(&self, id: &str) -> Option<&CollectorDef> {
+        self.collectors.iter().find(|c| c.id == id)
+    }
+
+    /// Project of a collector, from the catalog or the collector ID prefixes of the projects.
+    pub fn project_of(&self, collector_id: &str) -> Option<&str> {
+        if let Some(collector) = self.collector(collector_id) {
+            return Some(collector.project.as_str());
+        }
+        self.projects
+            .iter()
+            .find(|p| {
+                p.collector_prefixes
+                    .iter()
+                    .any(|prefix| collector_id.starts_with(prefix.as_str()))
+            })
+            .map(|p| p.name.as_str())
+    }
+
+    /// PostgREST `or` filter matching the `collector_id` of the files of a project.
+    pub fn collector_filter(&self, project: &str) -> String {
+        let mut conditions: Vec<String> = match self.project(project) {
+            Some(p) => p
+                .collector_prefixes
+                .iter()
+                .map(|prefix| format!("collector_id.like.\"{}*\"", prefix))
+                .collect(),
+            None => vec![],
+        };
+        let ids: Vec<String> = self
+            .collectors
+            .iter()
+            .filter(|c| c.project == project)
+            .map(|c| format!("\"{}\"", c.id))
+            .collect();
+        conditions.push(format!("collector_id.in.({})", ids.join(",")));
+        conditions.join(",")
+    }
+}
+
+/// The registry of projects and collectors, built-in unless [load_custom_registry] was called.
+pub(crate) fn collector_registry() -> &'static CollectorRegistry {
+    REGISTRY.get_or_init(CollectorRegistry::builtin)
+}
+
+/// Extend the built-in registry with custom projects and collectors from a JSON file.
+///
+/// Must be called before the registry is first used.
+pub(crate) fn load_custom_registry(path: &str) -> Result<(), String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let custom: CollectorRegistry =
+        serde_json::from_str(content.as_str()).map_err(|e| format!("{}: {}", path, e))?;
+    let mut registry = CollectorRegistry::builtin();
+    registry
+        .extend(custom)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    REGISTRY
+        .set(registry)
+        .map_err(|_| "collector registry already initialized".to_string())
+}
+
+/// Parse the `project` query parameter into a canonical project name, rejecting unknown projects.
+pub(crate) fn parse_project(project: &Option<String>) -> Result<Option<&'static str>, ApiError> {
+    let project = match project {
+        None => return Ok(None),
+        Some(p) => p,
+    };
+    let registry = collector_registry();
+    match registry.normalize_project(project) {
+        Some(name) => Ok(Some(name)),
+        None => Err(ApiError::new_bad_request(format!(
+            "unknown project: {}, valid values are {}",
+            project.trim(),
+            registry.project_names().join(", ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collector_registry() {
+        let mut registry = CollectorRegistry::builtin();
+        assert_eq!(registry.normalize_project("RIS"), Some("riperis"));
+        assert_eq!(registry.normalize_project("pch"), Some("pch"));
+        assert_eq!(registry.normalize_project("radb"), None);
+
+        assert_eq!(registry.project_of("rrc00"), Some("riperis"));
+        assert_eq!(registry.project_of("rrc27"), Some("riperis"));
+        assert_eq!(
+            registry.project_of("route-views.amsix"),
+            Some("route-views")
+        );
+        assert_eq!(registry.project_of("dagobah"), Some("isolario"));
+        assert_eq!(registry.project_of("my-collector"), None);
+        assert_eq!(
+            registry.collector_filter("riperis").split(',').next(),
+            Some("collector_id.like.\"rrc*\"")
+        );
+
+        let custom: CollectorRegistry = serde_json::from_str(
+            r#"{
+                "projects": [{"name": "Private", "aliases": ["acme"], "rib_cadence_minutes": 60}],
+                "collectors": [{"id": "my-collector", "project": "private", "location": "Berlin, DE"}]
+            }"#,
+        )
+        .unwrap();
+        registry.extend(custom).unwrap();
+        assert_eq!(registry.normalize_project("ACME"), Some("private"));
+        assert_eq!(registry.normalize_project("PRIVATE"), Some("private"));
+        assert_eq!(registry.project_of("my-collector"), Some("private"));
+        assert_eq!(
+            registry.collector("my-collector").unwrap().families.len(),
+            2
+        );
+        assert_eq!(
+            registry.collector_filter("private"),
+            "collector_id.in.(\"my-collector\")"
+        );
+
+        // collectors of unknown projects are rejected
+        let custom: CollectorRegistry = serde_json::from_str(
+            r#"{"collectors": [{"id": "other-collector", "project": "acme"}]}"#,
+        )
+        .unwrap();
+        assert!(registry.extend(custom).is_err());
+
+        assert!(parse_project(&Some("routeviews".to_string())).is_ok());
+        assert!(parse_project(&Some("radb".to_string())).is_err());
+    }
+}
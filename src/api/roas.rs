@@ -1,5 +1,5 @@
-use crate::api::Pagination;
-use crate::db::BgpkitDatabase;
+use crate::api::{parse_asn_list, parse_tals, ApiError, Pagination};
+use crate::db::{execute, fetch_pages, require_feature, BgpkitDatabase, DbFeature};
 use axum::extract::Query;
 use axum::{Extension, Json};
 use chrono::prelude::*;
@@ -9,119 +9,150 @@ use std::sync::Arc;
 use tracing::info;
 use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct RoasEntry {
     /// Autonomous system (AS) number
-    asn: u32,
+    pub asn: u32,
 
     /// maximum prefix length for this ROA
-    max_len: u32,
+    pub max_len: u32,
 
     /// prefix
-    prefix: String,
+    pub prefix: String,
 
     /// trust anchor locator
-    tal: String,
+    pub tal: String,
 
-    /// the ROA is still valid at least on previous day UTC.
-    current: bool,
+    /// the ROA is valid on the `as_of` date, by default the previous day UTC.
+    pub current: bool,
 
     /// ROA valid date ranges
-    date_ranges: Vec<Vec<String>>,
+    pub date_ranges: Vec<Vec<String>>,
+
+    /// whether valid date ranges were merged, including adjacent or overlapping ones
+    pub merged: bool,
+
+    /// total number of missing days bridged by merging
+    pub days_bridged: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RoasRawEntry {
     /// Autonomous system (AS) number
-    asn: u32,
+    pub asn: u32,
 
     /// maximum prefix length for this ROA
-    max_len: u32,
+    pub max_len: u32,
 
     /// prefix
-    prefix: String,
+    pub prefix: String,
 
     /// trust anchor locator
-    tal: String,
+    pub tal: String,
 
     /// ROA valid date ranges
-    date_ranges: Vec<String>,
+    pub date_ranges: Vec<String>,
+}
+
+/// number of ROAs fetched per request when collecting all matching ROAs
+const QUERY_HISTORY_PAGE_SIZE: usize = 10_000;
+
+/// The most recent date with complete ROA data, i.e. the previous day UTC.
+pub(crate) fn latest_roa_date() -> NaiveDate {
+    Utc::now().date_naive() - Duration::days(1)
+}
+
+/// Parse an optional date from a query parameter, defaulting to [latest_roa_date].
+pub(crate) fn parse_as_of(date: &Option<String>) -> Result<NaiveDate, ApiError> {
+    match date {
+        None => Ok(latest_roa_date()),
+        Some(d) => parse_query_date(d),
+    }
+}
+
+/// Parse a date from a query parameter, format: YYYY-MM-DD.
+pub(crate) fn parse_query_date(date: &str) -> Result<NaiveDate, ApiError> {
+    match NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+        Ok(d) => Ok(d),
+        Err(_) => Err(ApiError::new_bad_request(format!(
+            "cannot parse date string: {}",
+            date
+        ))),
+    }
+}
+
+/// Parse a PostgreSQL date range string, e.g. `[2022-01-01,2022-02-01)`, into inclusive first and
+/// last dates.
+pub(crate) fn parse_date_range(date_range: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let start_exclusive = date_range.starts_with('(');
+    let end_exclusive = date_range.ends_with(')');
+
+    let dates: Vec<&str> = date_range
+        .trim_matches(|c| char::is_ascii_punctuation(&c))
+        .split(',')
+        .collect();
+    if dates.len() != 2 {
+        return None;
+    }
+    let mut date_0 = NaiveDate::parse_from_str(dates[0].trim(), "%Y-%m-%d").ok()?;
+    let mut date_1 = NaiveDate::parse_from_str(dates[1].trim(), "%Y-%m-%d").ok()?;
+
+    if start_exclusive {
+        date_0 += Duration::days(1);
+    }
+    if end_exclusive {
+        date_1 -= Duration::days(1);
+    }
+    Some((date_0, date_1))
 }
 
 impl RoasRawEntry {
-    /// process raw ROAs database query results and fix single-day gaps if there is any
-    fn to_roas_entry(self, fix_gaps: bool) -> RoasEntry {
-        let mut current = false;
-        let mut date_ranges: Vec<Vec<Date<Utc>>> = self
-            .date_ranges
-            .into_iter()
-            .map(|date_range| {
-                let start_exclusive = date_range.starts_with('(');
-                let end_exclusive = date_range.ends_with(')');
-
-                let dates: Vec<&str> = (&date_range)
-                    .trim_matches(|c| char::is_ascii_punctuation(&c))
-                    .split(",")
-                    .collect();
-                let mut date_0 = Utc
-                    .datetime_from_str(
-                        format!("{}T00:00:00Z", dates[0]).as_str(),
-                        "%Y-%m-%dT%H:%M:%SZ",
-                    )
-                    .unwrap()
-                    .date();
-                let mut date_1 = Utc
-                    .datetime_from_str(
-                        format!("{}T00:00:00Z", dates[1]).as_str(),
-                        "%Y-%m-%dT%H:%M:%SZ",
-                    )
-                    .unwrap()
-                    .date();
-
-                if start_exclusive {
-                    date_0 = date_0 + Duration::days(1);
-                }
-                if end_exclusive {
-                    date_1 = date_1 - Duration::days(1);
-                }
-
-                if date_1 >= (Utc::now() - Duration::days(1)).date() {
-                    // The last valid day is at least one day before now
-                    current = true;
-                }
-
-                vec![date_0, date_1]
-            })
-            .collect();
+    /// the columns identifying a ROA, in the paging order of `query_history`
+    fn key(&self) -> (&str, u32, u32, &str) {
+        (
+            self.prefix.as_str(),
+            self.asn,
+            self.max_len,
+            self.tal.as_str(),
+        )
+    }
 
-        if fix_gaps {
-            info!("fixing gaps");
-            let mut cur_start = date_ranges[0][0];
-            let mut cur_end = date_ranges[0][1];
-            let mut new_ranges = vec![];
-
-            for i in 1..(date_ranges.len()) {
-                let date_0 = date_ranges[i][0];
-                let date_1 = date_ranges[i][1];
-                if cur_end == date_0 - Duration::days(2) {
-                    cur_end = date_1;
-                } else {
-                    new_ranges.push(vec![cur_start, cur_end]);
-                    cur_start = date_0;
-                    cur_end = date_1;
-                }
-            }
-            new_ranges.push(vec![cur_start, cur_end]);
+    /// inclusive first and last dates of each valid date range, malformed ranges are skipped
+    pub(crate) fn parsed_date_ranges(&self) -> Vec<(NaiveDate, NaiveDate)> {
+        self.date_ranges
+            .iter()
+            .filter_map(|r| parse_date_range(r.as_str()))
+            .collect()
+    }
 
-            date_ranges = new_ranges
-        }
+    /// whether the ROA is valid on `date`, after merging valid date ranges separated by gaps of at
+    /// most `merge_gaps` days
+    pub(crate) fn valid_on(&self, date: NaiveDate, merge_gaps: u32) -> bool {
+        let (ranges, _) = merge_date_ranges(self.parsed_date_ranges(), merge_gaps);
+        ranges
+            .iter()
+            .any(|(first, last)| *first <= date && date <= *last)
+    }
 
-        let date_ranges_strs: Vec<Vec<String>> = date_ranges
+    /// process raw ROAs database query results, merging valid date ranges separated by gaps of at
+    /// most `merge_gaps` days
+    ///
+    /// the ROA is marked current if it is valid on `as_of`
+    pub(crate) fn into_roas_entry(self, merge_gaps: u32, as_of: NaiveDate) -> RoasEntry {
+        let ranges = self.parsed_date_ranges();
+        let current = ranges
+            .iter()
+            .any(|(first, last)| *first <= as_of && as_of <= *last);
+        let range_count = ranges.len();
+        let (ranges, days_bridged) = merge_date_ranges(ranges, merge_gaps);
+        let merged = ranges.len() < range_count;
+
+        let date_ranges = ranges
             .into_iter()
-            .map(|range| {
+            .map(|(first, last)| {
                 vec![
-                    range[0].format("%Y-%m-%d").to_string(),
-                    range[1].format("%Y-%m-%d").to_string(),
+                    first.format("%Y-%m-%d").to_string(),
+                    last.format("%Y-%m-%d").to_string(),
                 ]
             })
             .collect();
@@ -132,9 +163,222 @@ impl RoasRawEntry {
             prefix: self.prefix,
             tal: self.tal,
             current,
-            date_ranges: date_ranges_strs,
+            date_ranges,
+            merged,
+            days_bridged,
+        }
+    }
+}
+
+/// Merge inclusive date ranges separated by gaps of at most `max_gap` missing days, e.g. caused by
+/// data collection outages. `0` disables merging.
+///
+/// Returns the merged ranges sorted by first date and the total number of missing days bridged.
+pub(crate) fn merge_date_ranges(
+    mut ranges: Vec<(NaiveDate, NaiveDate)>,
+    max_gap: u32,
+) -> (Vec<(NaiveDate, NaiveDate)>, u32) {
+    ranges.sort();
+    if max_gap == 0 {
+        return (ranges, 0);
+    }
+
+    let mut merged: Vec<(NaiveDate, NaiveDate)> = vec![];
+    let mut days_bridged = 0;
+    for (first, last) in ranges {
+        if let Some((_, cur_last)) = merged.last_mut() {
+            // missing days between the current range and this one, negative for overlaps
+            let gap = (first - *cur_last).num_days() - 1;
+            if gap <= max_gap as i64 {
+                days_bridged += gap.max(0) as u32;
+                *cur_last = (*cur_last).max(last);
+                continue;
+            }
+        }
+        merged.push((first, last));
+    }
+    (merged, days_bridged)
+}
+
+/// Parameters of the `query_history` database function.
+///
+/// Empty strings and `-1` disable the corresponding filters.
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct QueryHistoryParams {
+    pub res_limit: usize,
+    pub res_offset: usize,
+    pub prefix: String,
+    pub asn: i64,
+    pub max_len: i64,
+    pub nic: String,
+    pub date: String,
+    pub not_date: String,
+
+    /// multiple TALs, replacing `nic`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nics: Option<Vec<String>>,
+
+    /// multiple ASNs, replacing `asn`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asns: Option<Vec<u32>>,
+
+    /// prefix match mode, `valid` if not specified
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    pub match_mode: Option<String>,
+
+    /// first date of the valid date range filter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_from: Option<String>,
+
+    /// last date of the valid date range filter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_to: Option<String>,
+
+    /// `any` to match ROAs valid on any day of the date range, `all` to match ROAs valid on every
+    /// day of the date range
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_range_match: Option<String>,
+
+    /// address family of the ROA prefixes, `4` or `6`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<u8>,
+
+    /// minimum prefix length of the ROA prefixes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix_min_len: Option<u8>,
+
+    /// maximum prefix length of the ROA prefixes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix_max_len: Option<u8>,
+}
+
+impl QueryHistoryParams {
+    pub fn new(res_limit: usize, res_offset: usize) -> Self {
+        QueryHistoryParams {
+            res_limit,
+            res_offset,
+            prefix: "".to_string(),
+            asn: -1,
+            max_len: -1,
+            nic: "".to_string(),
+            date: "".to_string(),
+            not_date: "".to_string(),
+            nics: None,
+            asns: None,
+            match_mode: None,
+            date_from: None,
+            date_to: None,
+            date_range_match: None,
+            family: None,
+            prefix_min_len: None,
+            prefix_max_len: None,
+        }
+    }
+
+    /// filter by canonical TAL names, no filter if empty
+    pub fn set_tals(&mut self, tals: Vec<String>) {
+        match tals.len() {
+            0 => {}
+            1 => self.nic = tals[0].clone(),
+            _ => self.nics = Some(tals),
+        }
+    }
+
+    /// filter by ASNs, no filter if empty
+    pub fn set_asns(&mut self, asns: Vec<u32>) {
+        match asns.len() {
+            0 => {}
+            1 => self.asn = asns[0] as i64,
+            _ => self.asns = Some(asns),
         }
     }
+
+    /// whether any parameter beyond the baseline `query_history` function is set
+    pub fn uses_extensions(&self) -> bool {
+        self.nics.is_some()
+            || self.asns.is_some()
+            || self.match_mode.is_some()
+            || self.date_from.is_some()
+            || self.date_to.is_some()
+            || self.date_range_match.is_some()
+            || self.family.is_some()
+            || self.prefix_min_len.is_some()
+            || self.prefix_max_len.is_some()
+    }
+}
+
+/// Call the `query_history` database function and parse the raw ROA entries.
+pub(crate) async fn query_history(
+    db: &Arc<BgpkitDatabase>,
+    params: &QueryHistoryParams,
+) -> Result<Vec<RoasRawEntry>, ApiError> {
+    if params.uses_extensions() {
+        require_feature(DbFeature::RoaHistoryFilters)?;
+    }
+
+    // construct final RPC query string
+    let query_string = match serde_json::to_string(params) {
+        Ok(s) => s,
+        Err(_) => return Err(ApiError::new_internal("cannot construct ROA query")),
+    };
+    info!("{}", &query_string);
+
+    // execute RPC call, parsing large pages off the async runtime
+    let response = execute(db.client.rpc("query_history", query_string)).await?;
+    let parsed = tokio::task::spawn_blocking(move || {
+        serde_json::from_str::<Vec<RoasRawEntry>>(response.as_str())
+    })
+    .await;
+    match parsed {
+        Ok(Ok(data)) => Ok(data),
+        _ => Err(ApiError::new_internal("cannot parse database response")),
+    }
+}
+
+/// Call the `query_history` database function page by page and collect all raw ROA entries
+/// matching the filters, ignoring the limit and offset in `params`.
+///
+/// The function orders its results by prefix, ASN, max length and TAL before paging, see
+/// `sql/extensions.sql`. Entries are returned in that order without duplicates.
+pub(crate) async fn query_history_all(
+    db: &Arc<BgpkitDatabase>,
+    params: &QueryHistoryParams,
+) -> Result<Vec<RoasRawEntry>, ApiError> {
+    query_history_pages(db, params, None).await
+}
+
+/// Like [query_history_all], but fail with `400 Bad Request` once more than `max_entries` ROA
+/// entries match the filters.
+pub(crate) async fn query_history_bounded(
+    db: &Arc<BgpkitDatabase>,
+    params: &QueryHistoryParams,
+    max_entries: usize,
+) -> Result<Vec<RoasRawEntry>, ApiError> {
+    query_history_pages(db, params, Some(max_entries)).await
+}
+
+async fn query_history_pages(
+    db: &Arc<BgpkitDatabase>,
+    params: &QueryHistoryParams,
+    max_entries: Option<usize>,
+) -> Result<Vec<RoasRawEntry>, ApiError> {
+    let mut entries = fetch_pages(QUERY_HISTORY_PAGE_SIZE, max_entries, |offset, limit| {
+        let mut params = params.clone();
+        params.res_offset = offset;
+        params.res_limit = limit;
+        async move { query_history(db, &params).await }
+    })
+    .await?;
+    if let Some(max_entries) = max_entries.filter(|max| entries.len() > *max) {
+        return Err(ApiError::new_bad_request(format!(
+            "more than {} ROA entries match the query, narrow it down",
+            max_entries
+        )));
+    }
+    // guard against overlapping pages if the table changed while paging
+    entries.sort_by(|a, b| a.key().cmp(&b.key()));
+    entries.dedup_by(|a, b| a.key() == b.key());
+    Ok(entries)
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -147,36 +391,182 @@ pub struct RoasResponse {
 
 #[derive(Deserialize, IntoParams, Debug)]
 pub struct RoasSearchQuery {
-    /// filter results by ASN exact match
-    asn: Option<u32>,
+    /// filter results by ASN exact match, use comma to separate multiple ASNs
+    asn: Option<String>,
 
     /// IP prefix to search ROAs for, e.g. `?prefix=1.1.1.0/24`.
     prefix: Option<String>,
 
-    /// filer results by trusted anchor, supported values are `apnic`, `afrinic`, `lacnic`, `ripencc`, `arin`
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
     tal: Option<String>,
 
     /// limit the date of the ROAs, format: YYYY-MM-DD, e.g. `?date=2022-01-01`
     date: Option<String>,
 
-    /// filter results to whether ROA is still current
+    /// filter results to ROAs valid at any point in a date range starting on this date, format:
+    /// YYYY-MM-DD
+    from: Option<String>,
+
+    /// filter results to ROAs valid at any point in a date range ending on this date, format:
+    /// YYYY-MM-DD. defaults to `as_of` if `from` is set
+    to: Option<String>,
+
+    /// how ROAs are matched against the `from`/`to` date range: `any` (default) for ROAs valid at
+    /// any point in the range, `all` for ROAs valid during the whole range
+    range: Option<String>,
+
+    /// reference date for the `current` filter and the `current` flag of the results, format:
+    /// YYYY-MM-DD. defaults to the previous day UTC
+    as_of: Option<String>,
+
+    /// filter results to whether ROA is valid on the `as_of` date
     current: Option<bool>,
 
+    /// merge valid date ranges separated by at most this many missing days, `0` disables merging.
+    /// defaults to 1
+    merge_gaps: Option<u32>,
+
     /// filter results by the max_len value
     max_len: Option<u32>,
+
+    /// how ROAs are matched against `prefix`: `valid` (default) for ROAs validly covering the
+    /// prefix, `covering` for ROAs covering the prefix regardless of max length, `covered` for ROAs
+    /// of the prefix or more specifics, and `exact` for ROAs of the prefix only
+    #[serde(rename = "match")]
+    match_mode: Option<String>,
+
+    /// filter results by address family of the ROA prefix, `ipv4` or `ipv6`
+    family: Option<String>,
+
+    /// minimum prefix length of the ROA prefix
+    prefix_min_len: Option<u8>,
+
+    /// maximum prefix length of the ROA prefix
+    prefix_max_len: Option<u8>,
+}
+
+/// default maximum gap in days merged between valid date ranges of ROAs
+pub(crate) const DEFAULT_MERGE_GAPS: u32 = 1;
+
+/// prefix match modes supported by the `query_history` database function
+const MATCH_MODES: &[&str] = &["valid", "covering", "covered", "exact"];
+
+/// Parse a prefix match mode, rejecting unknown modes.
+pub(crate) fn parse_match_mode(match_mode: &Option<String>) -> Result<Option<String>, ApiError> {
+    match match_mode {
+        None => Ok(None),
+        Some(m) => {
+            let m = m.trim().to_lowercase();
+            match MATCH_MODES.contains(&m.as_str()) {
+                true => Ok(Some(m)),
+                false => Err(ApiError::new_bad_request(format!(
+                    "unknown match mode: {}, valid values are {}",
+                    m,
+                    MATCH_MODES.join(", ")
+                ))),
+            }
+        }
+    }
+}
+
+/// Parse an address family filter into the IP version number.
+pub(crate) fn parse_family(family: &Option<String>) -> Result<Option<u8>, ApiError> {
+    match family.as_deref().map(|f| f.trim().to_lowercase()) {
+        None => Ok(None),
+        Some(f) => match f.as_str() {
+            "ipv4" | "v4" | "4" => Ok(Some(4)),
+            "ipv6" | "v6" | "6" => Ok(Some(6)),
+            _ => Err(ApiError::new_bad_request(format!(
+                "unknown address family: {}, valid values are `ipv4` and `ipv6`",
+                f
+            ))),
+        },
+    }
+}
+
+/// Parse the `from`, `to` and `range` date range filters into first and last dates and range match
+/// mode, `to` defaulting to `as_of`.
+fn parse_date_range_filter(
+    from: &Option<String>,
+    to: &Option<String>,
+    range: &Option<String>,
+    as_of: NaiveDate,
+) -> Result<Option<(NaiveDate, NaiveDate, String)>, ApiError> {
+    let range = match range.as_deref().map(|r| r.trim().to_lowercase()) {
+        None => "any".to_string(),
+        Some(r) if r == "any" || r == "all" => r,
+        Some(r) => {
+            return Err(ApiError::new_bad_request(format!(
+                "unknown range match mode: {}, valid values are `any` and `all`",
+                r
+            )))
+        }
+    };
+    let (from, to) = match (from, to) {
+        (None, None) => return Ok(None),
+        (None, Some(_)) => {
+            return Err(ApiError::new_bad_request(
+                "`to` requires a `from` date to start the range",
+            ))
+        }
+        (Some(f), t) => (
+            parse_query_date(f)?,
+            match t {
+                Some(t) => parse_query_date(t)?,
+                None => as_of,
+            },
+        ),
+    };
+    if from > to {
+        return Err(ApiError::new_bad_request(
+            "the `from` date must not be later than the `to` date",
+        ));
+    }
+    Ok(Some((from, to, range)))
+}
+
+/// Validate a prefix length range against the address family.
+fn check_prefix_len_range(
+    family: Option<u8>,
+    prefix_min_len: Option<u8>,
+    prefix_max_len: Option<u8>,
+) -> Result<(), ApiError> {
+    let limit = match family {
+        Some(4) => 32,
+        _ => 128,
+    };
+    for len in [prefix_min_len, prefix_max_len].into_iter().flatten() {
+        if len > limit {
+            return Err(ApiError::new_bad_request(format!(
+                "prefix length {} out of range, must be at most {}",
+                len, limit
+            )));
+        }
+    }
+    if let (Some(min), Some(max)) = (prefix_min_len, prefix_max_len) {
+        if min > max {
+            return Err(ApiError::new_bad_request(
+                "`prefix_min_len` must not be greater than `prefix_max_len`",
+            ));
+        }
+    }
+    Ok(())
 }
 
 /// Search for information regarding autonomous systems.
 ///
-/// **NOTE**: only valid prefix match will be returned, i.e. the prefix must be contained within
-/// (or equals to) a prefix of a ROA entry and the length of the prefix must be equal or smaller
-/// than the max_length specified by the ROA.
+/// By default only valid prefix matches are returned, i.e. the prefix must be contained within (or
+/// equals to) a prefix of a ROA entry and the length of the prefix must be equal or smaller than the
+/// max_length specified by the ROA. Use `match` to find covering, covered or exactly matching ROAs
+/// instead.
 #[utoipa::path(
     get,
     tag = "bgp",
     path = "/roas",
     responses(
         (status = 200, description = "ROV information found", body = RoasResponse),
+        (status = 400, description = "invalid query parameters"),
     ),
     params(
         RoasSearchQuery,
@@ -187,113 +577,65 @@ pub async fn search_roas(
     Extension(db): Extension<Arc<BgpkitDatabase>>,
     query: Query<RoasSearchQuery>,
     pagination: Query<Pagination>,
-) -> Json<RoasResponse> {
+) -> Result<Json<RoasResponse>, ApiError> {
     // parse pagination parameters
     let (page, page_size) = pagination.extract(1000);
-    let offset = page * page_size;
-
-    let mut query_str_array = vec![
-        format!(r#""res_limit": {}"#, page_size),
-        format!(r#""res_offset": {}"#, offset),
-    ];
-    query_str_array.push(format!(
-        r#""prefix": {}"#,
-        match &query.prefix {
-            None => {
-                "\"\"".to_string()
-            }
-            Some(v) => {
-                format!("\"{}\"", v)
-            }
-        }
-    ));
-    query_str_array.push(format!(
-        r#""asn": {}"#,
-        match &query.asn {
-            None => {
-                "-1".to_string()
-            }
-            Some(v) => {
-                format!("{}", v)
-            }
-        }
-    ));
-    query_str_array.push(format!(
-        r#""max_len": {}"#,
-        match &query.max_len {
-            None => {
-                "-1".to_string()
-            }
-            Some(v) => {
-                format!("{}", v)
-            }
-        }
-    ));
-    query_str_array.push(format!(
-        r#""nic": {}"#,
-        match &query.tal {
-            None => {
-                "\"\"".to_string()
-            }
-            Some(v) => {
-                format!("\"{}\"", v)
-            }
-        }
-    ));
 
+    let match_mode = parse_match_mode(&query.match_mode)?;
+    if match_mode.is_some() && query.prefix.is_none() {
+        return Err(ApiError::new_bad_request(
+            "`match` requires a `prefix` to match against",
+        ));
+    }
+    let family = parse_family(&query.family)?;
+    check_prefix_len_range(family, query.prefix_min_len, query.prefix_max_len)?;
+
+    let mut params = QueryHistoryParams::new(page_size, page * page_size);
+    if let Some(prefix) = &query.prefix {
+        params.prefix = prefix.clone();
+    }
+    params.set_asns(
+        parse_asn_list(query.asn.as_deref().unwrap_or_default())?
+            .into_iter()
+            .collect(),
+    );
+    if let Some(max_len) = &query.max_len {
+        params.max_len = *max_len as i64;
+    }
+    params.match_mode = match_mode;
+    params.family = family;
+    params.prefix_min_len = query.prefix_min_len;
+    params.prefix_max_len = query.prefix_max_len;
+    params.set_tals(parse_tals(&query.tal)?);
+
+    let as_of = parse_as_of(&query.as_of)?;
+    let merge_gaps = query.merge_gaps.unwrap_or(DEFAULT_MERGE_GAPS);
+    let date_range = parse_date_range_filter(&query.from, &query.to, &query.range, as_of)?;
+    if date_range.is_some() && query.date.is_some() {
+        return Err(ApiError::new_bad_request(
+            "`date` cannot be combined with `from`/`to`",
+        ));
+    }
+    if let Some((from, to, range)) = date_range {
+        params.date_from = Some(from.to_string());
+        params.date_to = Some(to.to_string());
+        params.date_range_match = Some(range);
+    }
     match &query.current {
         None => {
-            query_str_array.push(format!(
-                r#""date": {}"#,
-                match &query.date {
-                    None => {
-                        "\"\"".to_string()
-                    }
-                    Some(v) => {
-                        format!("\"{}\"", v)
-                    }
-                }
-            ));
-            query_str_array.push(format!(r#""not_date": """#));
-        }
-        Some(current) => match current {
-            true => {
-                let date = (Utc::today() - Duration::days(1))
-                    .format("%Y-%m-%d")
-                    .to_string();
-                query_str_array.push(format!(r#""date": "{}""#, date));
-                query_str_array.push(format!(r#""not_date": """#));
-            }
-            false => {
-                let date = (Utc::today() - Duration::days(1))
-                    .format("%Y-%m-%d")
-                    .to_string();
-                query_str_array.push(format!(r#""not_date": "{}""#, date));
-                query_str_array.push(format!(r#""date": """#));
+            if let Some(date) = &query.date {
+                params.date = parse_query_date(date)?.to_string();
             }
-        },
+        }
+        Some(true) => params.date = as_of.to_string(),
+        Some(false) => params.not_date = as_of.to_string(),
     }
 
-    // construct final RPC query string
-    let query_string = format!("{{ {} }}", query_str_array.join(","));
-    info!("{}", &query_string);
-
-    // execute RPC call
-    let response = db
-        .client
-        .rpc("query_history", query_string)
-        .execute()
-        .await
-        .unwrap();
-
-    // gather response json text
-    let resp_text = response.text().await.unwrap();
-
     // convert date ranges to tuples
-    let raw_data: Vec<RoasRawEntry> = serde_json::from_str(resp_text.as_str()).unwrap();
+    let raw_data = query_history(&db, &params).await?;
     let data: Vec<RoasEntry> = raw_data
         .into_iter()
-        .map(|entry| entry.to_roas_entry(true))
+        .map(|entry| entry.into_roas_entry(merge_gaps, as_of))
         .collect();
 
     let count = data.len();
@@ -304,5 +646,221 @@ pub async fn search_roas(
         data,
     };
 
-    Json(response)
+    Ok(Json(response))
+}
+
+/// Fixtures shared by the tests of the ROA and VRP based endpoints.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use crate::api::{RoasRawEntry, Vrp, VrpSet};
+    use chrono::NaiveDate;
+    use ipnet::IpNet;
+    use std::str::FromStr;
+
+    pub(crate) fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    /// raw ROA entry of the `apnic` TAL, with date ranges as returned by PostgreSQL, e.g.
+    /// `[2023-01-01,2023-02-01)`
+    pub(crate) fn roa(prefix: &str, asn: u32, max_len: u32, date_ranges: &[&str]) -> RoasRawEntry {
+        RoasRawEntry {
+            asn,
+            max_len,
+            prefix: prefix.to_string(),
+            tal: "apnic".to_string(),
+            date_ranges: date_ranges.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    /// VRP of the `apnic` TAL
+    pub(crate) fn vrp(prefix: &str, max_len: u8, asn: u32) -> Vrp {
+        Vrp {
+            net: IpNet::from_str(prefix).unwrap(),
+            max_len,
+            asn,
+            tal: "apnic".to_string(),
+        }
+    }
+
+    /// VRP set of 2023-01-01 from `(prefix, max_len, asn)` tuples
+    pub(crate) fn vrp_set(vrps: &[(&str, u8, u32)]) -> VrpSet {
+        VrpSet::new(
+            date("2023-01-01"),
+            vrps.iter()
+                .map(|(prefix, max_len, asn)| vrp(prefix, *max_len, *asn))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::date;
+
+    #[test]
+    fn test_roas_filters() {
+        assert_eq!(
+            parse_match_mode(&Some("Covered".to_string())).unwrap(),
+            Some("covered".to_string())
+        );
+        assert!(parse_match_mode(&Some("overlap".to_string())).is_err());
+        assert_eq!(parse_family(&Some("ipv6".to_string())).unwrap(), Some(6));
+        assert!(parse_family(&Some("ipx".to_string())).is_err());
+
+        assert!(check_prefix_len_range(Some(4), Some(16), Some(24)).is_ok());
+        assert!(check_prefix_len_range(Some(4), Some(16), Some(48)).is_err());
+        assert!(check_prefix_len_range(None, Some(48), Some(32)).is_err());
+        let err = check_prefix_len_range(Some(6), Some(64), Some(48)).unwrap_err();
+        assert!(serde_json::to_string(&err)
+            .unwrap()
+            .contains("`prefix_min_len`"));
+    }
+
+    #[test]
+    fn test_as_of() {
+        let as_of = NaiveDate::from_ymd_opt(2023, 1, 10).unwrap();
+        let entry = RoasRawEntry {
+            asn: 13335,
+            max_len: 24,
+            prefix: "1.1.1.0/24".to_string(),
+            tal: "apnic".to_string(),
+            date_ranges: vec!["[2023-01-01,2023-01-11)".to_string()],
+        };
+        assert!(
+            entry
+                .clone()
+                .into_roas_entry(DEFAULT_MERGE_GAPS, as_of)
+                .current
+        );
+        assert!(
+            !entry
+                .into_roas_entry(DEFAULT_MERGE_GAPS, as_of + Duration::days(1))
+                .current
+        );
+
+        let (from, to, range) =
+            parse_date_range_filter(&Some("2023-01-01".to_string()), &None, &None, as_of)
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            (from.to_string(), to, range.as_str()),
+            ("2023-01-01".to_string(), as_of, "any")
+        );
+        assert!(
+            parse_date_range_filter(&None, &Some("2023-01-01".to_string()), &None, as_of).is_err()
+        );
+        assert!(parse_date_range_filter(
+            &Some("2023-02-01".to_string()),
+            &None,
+            &Some("all".to_string()),
+            as_of
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_date_range() {
+        // inclusive start, exclusive end, as returned by PostgreSQL
+        assert_eq!(
+            parse_date_range("[2022-01-01,2022-02-01)"),
+            Some((date("2022-01-01"), date("2022-01-31")))
+        );
+        // inclusive bounds
+        assert_eq!(
+            parse_date_range("[2022-01-01,2022-02-01]"),
+            Some((date("2022-01-01"), date("2022-02-01")))
+        );
+        // exclusive bounds
+        assert_eq!(
+            parse_date_range("(2022-01-01,2022-02-01)"),
+            Some((date("2022-01-02"), date("2022-01-31")))
+        );
+        // single day
+        assert_eq!(
+            parse_date_range("[2022-01-01,2022-01-02)"),
+            Some((date("2022-01-01"), date("2022-01-01")))
+        );
+
+        assert_eq!(parse_date_range("[2022-01-01)"), None);
+        assert_eq!(parse_date_range("[2022-01-01,2022-13-01)"), None);
+        assert_eq!(parse_date_range(""), None);
+    }
+
+    #[test]
+    fn test_merge_date_ranges() {
+        let ranges = vec![
+            (date("2022-03-01"), date("2022-03-10")),
+            (date("2022-01-01"), date("2022-01-10")),
+            // one missing day
+            (date("2022-01-12"), date("2022-01-20")),
+            // three missing days
+            (date("2022-01-24"), date("2022-02-01")),
+        ];
+
+        // single range is kept as is
+        let (merged, bridged) = merge_date_ranges(ranges[..1].to_vec(), 1);
+        assert_eq!(merged, ranges[..1].to_vec());
+        assert_eq!(bridged, 0);
+
+        let (merged, bridged) = merge_date_ranges(ranges.clone(), 0);
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[0].0, date("2022-01-01"));
+        assert_eq!(bridged, 0);
+
+        let (merged, bridged) = merge_date_ranges(ranges.clone(), 1);
+        assert_eq!(
+            merged,
+            vec![
+                (date("2022-01-01"), date("2022-01-20")),
+                (date("2022-01-24"), date("2022-02-01")),
+                (date("2022-03-01"), date("2022-03-10")),
+            ]
+        );
+        assert_eq!(bridged, 1);
+
+        let (merged, bridged) = merge_date_ranges(ranges, 3);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0], (date("2022-01-01"), date("2022-02-01")));
+        assert_eq!(bridged, 4);
+    }
+
+    #[test]
+    fn test_into_roas_entry_merge_info() {
+        let entry = RoasRawEntry {
+            asn: 13335,
+            max_len: 24,
+            prefix: "1.1.1.0/24".to_string(),
+            tal: "apnic".to_string(),
+            date_ranges: vec![
+                "[2022-01-01,2022-01-11)".to_string(),
+                "[2022-01-12,2022-01-21)".to_string(),
+            ],
+        };
+        let roa = entry.clone().into_roas_entry(1, date("2022-01-15"));
+        assert!(roa.merged);
+        assert_eq!(roa.days_bridged, 1);
+        assert_eq!(
+            roa.date_ranges,
+            vec![vec!["2022-01-01".to_string(), "2022-01-20".to_string()]]
+        );
+
+        let roa = entry.clone().into_roas_entry(0, date("2022-01-15"));
+        assert!(!roa.merged);
+        assert_eq!(roa.date_ranges.len(), 2);
+
+        // adjacent ranges are merged without bridging any day
+        let entry = RoasRawEntry {
+            date_ranges: vec![
+                "[2022-01-01,2022-01-11)".to_string(),
+                "[2022-01-11,2022-01-21)".to_string(),
+            ],
+            ..entry
+        };
+        let roa = entry.into_roas_entry(1, date("2022-01-15"));
+        assert!(roa.merged);
+        assert_eq!(roa.days_bridged, 0);
+        assert_eq!(roa.date_ranges.len(), 1);
+    }
 }
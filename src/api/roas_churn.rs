@@ -0,0 +1,419 @@
+use crate::api::{
+    fetch_announced_prefixes, latest_roa_date, merge_date_ranges, parse_asn_list, parse_query_date,
+    parse_tals, query_history_all, rows_response, ApiError, CsvRow, OutputFormat, Pagination,
+    QueryHistoryParams, RoasRawEntry, DEFAULT_MERGE_GAPS,
+};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// longest supported churn window in days
+const MAX_CHURN_DAYS: i64 = 92;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RoaChange {
+    /// prefix
+    prefix: String,
+
+    /// Autonomous system (AS) number
+    asn: u32,
+
+    /// maximum prefix length for this ROA
+    max_len: u32,
+
+    /// trust anchor locator
+    tal: String,
+
+    /// first day the ROA was valid for new ROAs, first day it was no longer valid for expired ROAs
+    date: String,
+
+    /// the ROA is valid on the `until` date
+    current: bool,
+
+    /// whether the exact prefix is announced in BGP on the `until` date, or currently if `until` is
+    /// not set. only set when filtering with `announced`
+    announced: Option<bool>,
+}
+
+impl CsvRow for RoaChange {
+    const COLUMNS: &'static [&'static str] = &[
+        "prefix",
+        "asn",
+        "max_len",
+        "tal",
+        "date",
+        "current",
+        "announced",
+    ];
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RoaChurnResponse {
+    since: String,
+    until: String,
+    page: usize,
+    page_size: usize,
+
+    /// total number of ROAs matching the filters
+    total: usize,
+    count: usize,
+    data: Vec<RoaChange>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RoaChurnQuery {
+    /// start of the window, either a date (YYYY-MM-DD) or a duration before `until`, e.g. `7d` or
+    /// `2weeks`
+    since: String,
+
+    /// last date of the window, format: YYYY-MM-DD. defaults to the previous day UTC
+    until: Option<String>,
+
+    /// filter results by ASN, use comma to separate multiple ASNs
+    asn: Option<String>,
+
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// `true` to only return ROAs whose prefix is announced in BGP, `false` to only return ROAs whose
+    /// prefix is not announced. announcements are matched on the exact prefix, on the `until` date
+    /// if set or in the latest data otherwise
+    announced: Option<bool>,
+
+    /// output format: `json` (default), `csv` or `ndjson`. CSV and NDJSON output contain all matching
+    /// ROAs, ignoring pagination
+    format: Option<String>,
+}
+
+/// ROA history entries valid at some point of a churn window, including the day before it.
+pub struct RoaChurnEntries {
+    entries: Vec<RoasRawEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChurnKind {
+    Expired,
+    New,
+}
+
+/// Parse the `since` parameter as a date or as a duration before `until`.
+fn parse_since(since: &str, until: NaiveDate) -> Result<NaiveDate, ApiError> {
+    if let Ok(date) = parse_query_date(since) {
+        return Ok(date);
+    }
+    match humantime::parse_duration(since.trim()) {
+        Ok(duration) => {
+            let days = (duration.as_secs() / 86400).max(1) as i64;
+            Ok(until - Duration::days(days))
+        }
+        Err(_) => Err(ApiError::new_bad_request(format!(
+            "cannot parse since: {}, use a date (YYYY-MM-DD) or a duration such as `7d`",
+            since
+        ))),
+    }
+}
+
+/// Find ROAs that expired or became valid between `since` and `until`, inclusive.
+///
+/// Valid date ranges are merged first, so data collection outages do not show up as churn. An
+/// expired ROA is one whose last valid day is before `until` and whose first invalid day is in the
+/// window. A new ROA is one with a valid date range starting in the window.
+fn roa_changes(
+    entries: &[RoasRawEntry],
+    kind: ChurnKind,
+    since: NaiveDate,
+    until: NaiveDate,
+) -> Vec<RoaChange> {
+    let mut changes: Vec<RoaChange> = entries
+        .iter()
+        .filter_map(|entry| {
+            let (ranges, _) = merge_date_ranges(entry.parsed_date_ranges(), DEFAULT_MERGE_GAPS);
+            let current = ranges
+                .iter()
+                .any(|(first, last)| *first <= until && until <= *last);
+            let date = match kind {
+                ChurnKind::Expired => {
+                    let (_, last) = ranges.iter().rfind(|(first, _)| *first <= until)?;
+                    let ended = *last + Duration::days(1);
+                    match !current && since <= ended && ended <= until {
+                        true => ended,
+                        false => return None,
+                    }
+                }
+                ChurnKind::New => ranges
+                    .iter()
+                    .map(|(first, _)| *first)
+                    .find(|first| since <= *first && *first <= until)?,
+            };
+            Some(RoaChange {
+                prefix: entry.prefix.clone(),
+                asn: entry.asn,
+                max_len: entry.max_len,
+                tal: entry.tal.clone(),
+                date: date.to_string(),
+                current,
+                announced: None,
+            })
+        })
+        .collect();
+    // most recent changes first
+    changes.sort_by(|a, b| {
+        b.date
+            .cmp(&a.date)
+            .then_with(|| a.prefix.cmp(&b.prefix))
+            .then_with(|| a.asn.cmp(&b.asn))
+    });
+    changes
+}
+
+/// Load the ROA history entries of a churn window, caching them on first use.
+async fn load_churn_entries(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<RoaChurnEntries>,
+    since: NaiveDate,
+    until: NaiveDate,
+    asns: Vec<u32>,
+    tals: Vec<String>,
+) -> Result<Arc<RoaChurnEntries>, ApiError> {
+    let asn_key: Vec<String> = asns.iter().map(|asn| asn.to_string()).collect();
+    let key = format!(
+        "{}/{}/{}/{}",
+        since,
+        until,
+        asn_key.join(","),
+        tals.join(",")
+    );
+    cache
+        .get_or_try_insert_with(key.as_str(), || async {
+            // expired ROAs were valid on the day before the window at the latest
+            let mut params = QueryHistoryParams::new(0, 0);
+            params.date_from = Some((since - Duration::days(1)).to_string());
+            params.date_to = Some(until.to_string());
+            params.date_range_match = Some("any".to_string());
+            params.set_asns(asns);
+            params.set_tals(tals);
+            let entries = query_history_all(db, &params).await?;
+            Ok(RoaChurnEntries { entries })
+        })
+        .await
+}
+
+async fn search_roa_churn(
+    db: Arc<BgpkitDatabase>,
+    cache: Arc<TtlCache<RoaChurnEntries>>,
+    kind: ChurnKind,
+    query: &RoaChurnQuery,
+    pagination: &Pagination,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::parse(&query.format)?;
+    let (page, page_size) = pagination.extract(10_000);
+    let until = match &query.until {
+        Some(until) => parse_query_date(until)?,
+        None => latest_roa_date(),
+    };
+    let since = parse_since(query.since.as_str(), until)?;
+    if since > until {
+        return Err(ApiError::new_bad_request(
+            "the `since` date must not be later than the `until` date",
+        ));
+    }
+    if (until - since).num_days() >= MAX_CHURN_DAYS {
+        return Err(ApiError::new_bad_request(format!(
+            "window too long, at most {} days are supported",
+            MAX_CHURN_DAYS
+        )));
+    }
+
+    let asns: Vec<u32> = parse_asn_list(query.asn.as_deref().unwrap_or_default())?
+        .into_iter()
+        .collect();
+    let tals = parse_tals(&query.tal)?;
+    let churn = load_churn_entries(&db, &cache, since, until, asns, tals).await?;
+    let mut changes = roa_changes(&churn.entries, kind, since, until);
+
+    if let Some(announced) = query.announced {
+        let mut prefixes: Vec<String> = changes.iter().map(|c| c.prefix.clone()).collect();
+        prefixes.sort();
+        prefixes.dedup();
+        // historical windows are checked against the announcements of their last day
+        let pfx2as_date = query.until.as_ref().map(|_| until);
+        let announced_prefixes = fetch_announced_prefixes(&db, &prefixes, pfx2as_date).await?;
+        changes.retain_mut(|change| {
+            let is_announced = announced_prefixes.contains(&change.prefix);
+            change.announced = Some(is_announced);
+            is_announced == announced
+        });
+    }
+
+    if format != OutputFormat::Json {
+        return Ok(rows_response(format, &changes));
+    }
+    let total = changes.len();
+    let data: Vec<RoaChange> = changes
+        .into_iter()
+        .skip(page * page_size)
+        .take(page_size)
+        .collect();
+    Ok(Json(RoaChurnResponse {
+        since: since.to_string(),
+        until: until.to_string(),
+        page,
+        page_size,
+        total,
+        count: data.len(),
+        data,
+    })
+    .into_response())
+}
+
+/// Recently expired ROAs.
+///
+/// Lists ROAs whose validity ended within the window and that are not valid on its last date, most
+/// recent first. Use `announced=true` to only list ROAs whose prefix is still announced in BGP.
+/// Windows are limited to 92 days.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/roas/expired",
+    responses(
+        (status = 200, description = "recently expired ROAs", body = RoaChurnResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        RoaChurnQuery,
+        Pagination,
+    )
+)]
+pub async fn search_expired_roas(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<RoaChurnEntries>>>,
+    query: Query<RoaChurnQuery>,
+    pagination: Query<Pagination>,
+) -> Result<Response, ApiError> {
+    search_roa_churn(db, cache, ChurnKind::Expired, &query, &pagination).await
+}
+
+/// Recently created ROAs.
+///
+/// Lists ROAs whose validity began within the window, most recent first. Use `announced=true` to
+/// only list ROAs whose prefix is announced in BGP. Windows are limited to 92 days.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/roas/new",
+    responses(
+        (status = 200, description = "recently created ROAs", body = RoaChurnResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        RoaChurnQuery,
+        Pagination,
+    )
+)]
+pub async fn search_new_roas(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<RoaChurnEntries>>>,
+    query: Query<RoaChurnQuery>,
+    pagination: Query<Pagination>,
+) -> Result<Response, ApiError> {
+    search_roa_churn(db, cache, ChurnKind::New, &query, &pagination).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::{date, roa};
+
+    #[test]
+    fn test_roa_changes() {
+        let entries = vec![
+            // expired in the window
+            roa("1.1.1.0/24", 64500, 24, &["[2023-01-01,2023-03-05)"]),
+            // one-day outage, merged and still current
+            roa(
+                "1.1.2.0/24",
+                64500,
+                24,
+                &["[2023-01-01,2023-03-05)", "[2023-03-06,2023-03-11)"],
+            ),
+            // created in the window
+            roa("1.1.3.0/24", 64500, 24, &["[2023-03-07,2023-03-11)"]),
+            // expired long ago
+            roa("1.1.4.0/24", 64500, 24, &["[2022-01-01,2022-02-01)"]),
+        ];
+        let (since, until) = (date("2023-03-01"), date("2023-03-10"));
+
+        let expired = roa_changes(&entries, ChurnKind::Expired, since, until);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].prefix, "1.1.1.0/24");
+        assert_eq!(expired[0].date, "2023-03-05");
+
+        let new = roa_changes(&entries, ChurnKind::New, since, until);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].prefix, "1.1.3.0/24");
+        assert!(new[0].current);
+
+        assert_eq!(parse_since("7d", until).unwrap(), date("2023-03-03"));
+        assert_eq!(
+            parse_since("2023-02-01", until).unwrap(),
+            date("2023-02-01")
+        );
+        assert!(parse_since("yesterday", until).is_err());
+    }
+
+    #[test]
+    fn test_roa_changes_window_bounds() {
+        let (since, until) = (date("2023-03-01"), date("2023-03-10"));
+        assert!(roa_changes(&[], ChurnKind::Expired, since, until).is_empty());
+
+        let entries = vec![
+            // first invalid day on `since` and on `until` are both in the window
+            roa("1.1.1.0/24", 64500, 24, &["[2023-01-01,2023-03-01)"]),
+            roa("1.1.2.0/24", 64500, 24, &["[2023-01-01,2023-03-10)"]),
+            // first invalid day before the window
+            roa("1.1.3.0/24", 64500, 24, &["[2023-01-01,2023-02-28)"]),
+            // revoked and re-created within the window, so not expired
+            roa(
+                "1.1.4.0/24",
+                64500,
+                24,
+                &["[2023-01-01,2023-03-03)", "[2023-03-06,2023-03-11)"],
+            ),
+            // valid range starting after `until`
+            roa("1.1.5.0/24", 64500, 24, &["[2023-03-11,2023-03-12)"]),
+            // same change date, ordered by prefix then ASN
+            roa("1.1.6.0/24", 64501, 24, &["[2023-03-10,2023-03-11)"]),
+            roa("1.1.6.0/24", 64500, 24, &["[2023-03-10,2023-03-11)"]),
+        ];
+
+        let expired = roa_changes(&entries, ChurnKind::Expired, since, until);
+        let dates: Vec<(&str, &str)> = expired
+            .iter()
+            .map(|c| (c.prefix.as_str(), c.date.as_str()))
+            .collect();
+        assert_eq!(
+            dates,
+            vec![("1.1.2.0/24", "2023-03-10"), ("1.1.1.0/24", "2023-03-01")]
+        );
+
+        let new = roa_changes(&entries, ChurnKind::New, since, until);
+        let created: Vec<(&str, u32)> = new.iter().map(|c| (c.prefix.as_str(), c.asn)).collect();
+        assert_eq!(
+            created,
+            vec![
+                ("1.1.6.0/24", 64500),
+                ("1.1.6.0/24", 64501),
+                ("1.1.4.0/24", 64500),
+            ]
+        );
+    }
+}
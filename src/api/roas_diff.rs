@@ -0,0 +1,310 @@
+use crate::api::{
+    parse_asn_list, parse_query_date, parse_tals, query_history_all, rows_response, ApiError,
+    CsvRow, OutputFormat, QueryHistoryParams, RoasRawEntry, DEFAULT_MERGE_GAPS,
+};
+use crate::db::BgpkitDatabase;
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RoasDiffEntry {
+    /// type of change: `added`, `removed` or `max_len_changed`
+    change: String,
+
+    /// prefix
+    prefix: String,
+
+    /// Autonomous system (AS) number
+    asn: u32,
+
+    /// trust anchor locator
+    tal: String,
+
+    /// maximum prefix length, on the `to` date for added and changed ROAs and on the `from` date
+    /// for removed ROAs
+    max_len: u32,
+
+    /// maximum prefix length on the `from` date, only set for changed ROAs
+    previous_max_len: Option<u32>,
+}
+
+impl CsvRow for RoasDiffEntry {
+    const COLUMNS: &'static [&'static str] = &[
+        "change",
+        "prefix",
+        "asn",
+        "tal",
+        "max_len",
+        "previous_max_len",
+    ];
+}
+
+#[derive(Serialize, Deserialize, Default, ToSchema)]
+pub struct RoasDiffCounts {
+    added: usize,
+    removed: usize,
+    max_len_changed: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RoasDiffResponse {
+    from: String,
+    to: String,
+    counts: RoasDiffCounts,
+
+    /// ROAs valid on the `to` date but not on the `from` date
+    added: Vec<RoasDiffEntry>,
+
+    /// ROAs valid on the `from` date but not on the `to` date
+    removed: Vec<RoasDiffEntry>,
+
+    /// ROAs for the same prefix, ASN and TAL whose max length changed between the two dates
+    max_len_changed: Vec<RoasDiffEntry>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RoasDiffQuery {
+    /// the earlier date to compare, format: YYYY-MM-DD
+    from: String,
+
+    /// the later date to compare, format: YYYY-MM-DD
+    to: String,
+
+    /// filter results by ASN exact match, use comma to separate multiple ASNs
+    asn: Option<String>,
+
+    /// IP prefix to search ROAs for, e.g. `?prefix=1.1.1.0/24`.
+    prefix: Option<String>,
+
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// output format: `json` (default), `csv` or `ndjson`. CSV and NDJSON output contain one row per
+    /// changed ROA
+    format: Option<String>,
+}
+
+fn diff_entry(entry: &RoasRawEntry, change: &str, previous_max_len: Option<u32>) -> RoasDiffEntry {
+    RoasDiffEntry {
+        change: change.to_string(),
+        prefix: entry.prefix.clone(),
+        asn: entry.asn,
+        tal: entry.tal.clone(),
+        max_len: entry.max_len,
+        previous_max_len,
+    }
+}
+
+/// ROAs of the same prefix, ASN and TAL that were removed or added between two dates
+#[derive(Default)]
+struct GroupChanges<'a> {
+    removed: Vec<&'a RoasRawEntry>,
+    added: Vec<&'a RoasRawEntry>,
+}
+
+/// Compare the ROAs valid on two dates, based on the valid date ranges of each ROA.
+///
+/// Valid date ranges are merged over gaps of [DEFAULT_MERGE_GAPS] days first, so data collection
+/// outages on either date do not show up as changes. Returns added, removed and max length changed
+/// entries. A ROA removed and another added for the same prefix, ASN and TAL are reported as a max
+/// length change instead.
+fn diff_roas(
+    entries: &[RoasRawEntry],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> (Vec<RoasDiffEntry>, Vec<RoasDiffEntry>, Vec<RoasDiffEntry>) {
+    // removed and added ROAs grouped by prefix, ASN and TAL
+    let mut groups: BTreeMap<(String, u32, String), GroupChanges> = BTreeMap::new();
+    for entry in entries {
+        let (valid_from, valid_to) = (
+            entry.valid_on(from, DEFAULT_MERGE_GAPS),
+            entry.valid_on(to, DEFAULT_MERGE_GAPS),
+        );
+        if valid_from == valid_to {
+            continue;
+        }
+        let group = groups
+            .entry((entry.prefix.clone(), entry.asn, entry.tal.clone()))
+            .or_default();
+        match valid_from {
+            true => group.removed.push(entry),
+            false => group.added.push(entry),
+        }
+    }
+
+    let (mut added, mut removed, mut changed) = (vec![], vec![], vec![]);
+    for (_, group) in groups {
+        let (mut group_removed, mut group_added) = (group.removed, group.added);
+        group_removed.sort_by_key(|e| e.max_len);
+        group_added.sort_by_key(|e| e.max_len);
+        let paired = group_removed.len().min(group_added.len());
+        for (old, new) in group_removed.iter().zip(group_added.iter()) {
+            changed.push(diff_entry(new, "max_len_changed", Some(old.max_len)));
+        }
+        for entry in group_removed.iter().skip(paired) {
+            removed.push(diff_entry(entry, "removed", None));
+        }
+        for entry in group_added.iter().skip(paired) {
+            added.push(diff_entry(entry, "added", None));
+        }
+    }
+    (added, removed, changed)
+}
+
+/// Changes of ROAs between two dates.
+///
+/// Lists ROAs that appeared or disappeared between the `from` and `to` dates, and ROAs whose max
+/// length changed.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/roas/diff",
+    responses(
+        (status = 200, description = "ROA changes between two dates", body = RoasDiffResponse),
+        (status = 400, description = "invalid query parameters"),
+    ),
+    params(
+        RoasDiffQuery,
+    )
+)]
+pub async fn diff_roas_between(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    query: Query<RoasDiffQuery>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::parse(&query.format)?;
+    let from = parse_query_date(query.from.as_str())?;
+    let to = parse_query_date(query.to.as_str())?;
+    if from > to {
+        return Err(ApiError::new_bad_request(
+            "the `from` date must not be later than the `to` date",
+        ));
+    }
+
+    let mut params = QueryHistoryParams::new(0, 0);
+    if let Some(prefix) = &query.prefix {
+        params.prefix = prefix.clone();
+    }
+    params.set_asns(
+        parse_asn_list(query.asn.as_deref().unwrap_or_default())?
+            .into_iter()
+            .collect(),
+    );
+    params.set_tals(parse_tals(&query.tal)?);
+
+    // a ROA in a merged gap on either date is valid within the preceding gap days
+    let mut dates: Vec<NaiveDate> = (0..=DEFAULT_MERGE_GAPS as i64)
+        .flat_map(|days| [from - Duration::days(days), to - Duration::days(days)])
+        .collect();
+    dates.sort();
+    dates.dedup();
+    let mut entries = vec![];
+    for date in dates {
+        params.date = date.to_string();
+        entries.extend(query_history_all(&db, &params).await?);
+    }
+    entries.sort_by(|a, b| {
+        (&a.prefix, a.asn, &a.tal, a.max_len).cmp(&(&b.prefix, b.asn, &b.tal, b.max_len))
+    });
+    entries.dedup_by(|a, b| {
+        (&a.prefix, a.asn, &a.tal, a.max_len) == (&b.prefix, b.asn, &b.tal, b.max_len)
+    });
+
+    let (added, removed, max_len_changed) = diff_roas(&entries, from, to);
+
+    if format != OutputFormat::Json {
+        let rows: Vec<RoasDiffEntry> = added
+            .into_iter()
+            .chain(removed)
+            .chain(max_len_changed)
+            .collect();
+        return Ok(rows_response(format, &rows));
+    }
+
+    Ok(Json(RoasDiffResponse {
+        from: from.to_string(),
+        to: to.to_string(),
+        counts: RoasDiffCounts {
+            added: added.len(),
+            removed: removed.len(),
+            max_len_changed: max_len_changed.len(),
+        },
+        added,
+        removed,
+        max_len_changed,
+    })
+    .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::roa;
+
+    #[test]
+    fn test_diff_roas() {
+        let entries = vec![
+            // unchanged
+            roa("1.1.1.0/24", 13335, 24, &["[2023-01-01,2023-03-01)"]),
+            // removed
+            roa("1.0.0.0/24", 13335, 24, &["[2023-01-01,2023-01-15)"]),
+            // added
+            roa("1.0.4.0/22", 64500, 24, &["[2023-01-20,2023-03-01)"]),
+            // max_len changed from 24 to 23
+            roa("1.0.8.0/22", 64501, 24, &["[2023-01-01,2023-01-10)"]),
+            roa("1.0.8.0/22", 64501, 23, &["[2023-01-10,2023-03-01)"]),
+        ];
+        let from = NaiveDate::from_ymd_opt(2023, 1, 5).unwrap();
+        let to = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap();
+        let (added, removed, changed) = diff_roas(&entries, from, to);
+
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].prefix, "1.0.4.0/22");
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].prefix, "1.0.0.0/24");
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].max_len, 23);
+        assert_eq!(changed[0].previous_max_len, Some(24));
+    }
+
+    #[test]
+    fn test_diff_roas_collection_gaps() {
+        let entries = vec![
+            // data missing on 2023-02-01, the `to` date
+            roa(
+                "1.1.1.0/24",
+                13335,
+                24,
+                &["[2023-01-01,2023-02-01)", "[2023-02-02,2023-03-01)"],
+            ),
+            // data missing on 2023-01-05, the `from` date
+            roa(
+                "1.0.0.0/24",
+                13335,
+                24,
+                &["[2023-01-01,2023-01-05)", "[2023-01-06,2023-03-01)"],
+            ),
+            // revoked for two days around the `to` date
+            roa(
+                "1.0.4.0/22",
+                64500,
+                24,
+                &["[2023-01-01,2023-01-31)", "[2023-02-02,2023-03-01)"],
+            ),
+        ];
+        let from = NaiveDate::from_ymd_opt(2023, 1, 5).unwrap();
+        let to = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap();
+        let (added, removed, changed) = diff_roas(&entries, from, to);
+
+        assert!(added.is_empty());
+        assert!(changed.is_empty());
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].prefix, "1.0.4.0/22");
+    }
+}
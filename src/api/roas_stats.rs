@@ -0,0 +1,378 @@
+use crate::api::{
+    address_space, latest_roa_date, parse_query_date, parse_tals, query_history_all, rows_response,
+    ApiError, CsvRow, OutputFormat, QueryHistoryParams,
+};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{Datelike, Duration, NaiveDate};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// maximum number of days of the statistics date range, both ends included
+const MAX_STATS_SPAN_DAYS: i64 = 366;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RoaStats {
+    /// date of the ROA snapshot, the last day of the period for weekly and monthly statistics
+    date: String,
+
+    /// trust anchor locator
+    tal: String,
+
+    /// address family, `ipv4` or `ipv6`
+    family: String,
+
+    /// number of ROAs
+    roas: usize,
+
+    /// number of unique ASNs
+    asns: usize,
+
+    /// number of unique prefixes
+    prefixes: usize,
+
+    /// covered address space with overlapping prefixes counted once, in /24 equivalents for IPv4
+    /// and /48 equivalents for IPv6
+    address_space: f64,
+}
+
+impl CsvRow for RoaStats {
+    const COLUMNS: &'static [&'static str] = &[
+        "date",
+        "tal",
+        "family",
+        "roas",
+        "asns",
+        "prefixes",
+        "address_space",
+    ];
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RoaStatsResponse {
+    from: String,
+    to: String,
+    interval: String,
+    count: usize,
+    data: Vec<RoaStats>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RoaStatsQuery {
+    /// first date of the statistics, format: YYYY-MM-DD
+    from: String,
+
+    /// last date of the statistics, format: YYYY-MM-DD. defaults to the previous day UTC
+    to: Option<String>,
+
+    /// `day` (default), `week` or `month`. weekly and monthly statistics are computed on the last
+    /// day of each period
+    interval: Option<String>,
+
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// output format: `json` (default), `csv` or `ndjson`
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatsInterval {
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsInterval {
+    fn parse(interval: &Option<String>) -> Result<Self, ApiError> {
+        match interval.as_deref().map(|i| i.trim().to_lowercase()) {
+            None => Ok(StatsInterval::Day),
+            Some(i) => match i.as_str() {
+                "day" | "daily" => Ok(StatsInterval::Day),
+                "week" | "weekly" => Ok(StatsInterval::Week),
+                "month" | "monthly" => Ok(StatsInterval::Month),
+                _ => Err(ApiError::new_bad_request(format!(
+                    "unknown interval: {}, valid values are `day`, `week` and `month`",
+                    i
+                ))),
+            },
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            StatsInterval::Day => "day",
+            StatsInterval::Week => "week",
+            StatsInterval::Month => "month",
+        }
+    }
+
+    /// last day of the period containing `date`
+    fn period_end(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            StatsInterval::Day => date,
+            StatsInterval::Week => {
+                date + Duration::days(6 - date.weekday().num_days_from_monday() as i64)
+            }
+            StatsInterval::Month => {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    m => (date.year(), m + 1),
+                };
+                NaiveDate::from_ymd_opt(year, month, 1).unwrap() - Duration::days(1)
+            }
+        }
+    }
+
+    /// dates on which statistics are computed between `from` and `to`, inclusive
+    fn sample_dates(&self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = vec![];
+        let mut date = from;
+        while date <= to {
+            let end = self.period_end(date);
+            dates.push(end.min(to));
+            date = end + Duration::days(1);
+        }
+        dates
+    }
+}
+
+/// ROA statistics of all TALs on the sample dates of a date range and interval.
+pub struct RoaStatsSet {
+    stats: Vec<RoaStats>,
+}
+
+/// ROA with parsed prefix and valid date ranges
+struct StatsRoa {
+    net: IpNet,
+    asn: u32,
+    tal: String,
+    ranges: Vec<(NaiveDate, NaiveDate)>,
+}
+
+/// Compute ROA statistics per date, TAL and address family.
+fn compute_stats(roas: &[StatsRoa], dates: &[NaiveDate]) -> Vec<RoaStats> {
+    let mut stats = vec![];
+    for date in dates {
+        let mut groups: BTreeMap<(&str, &str), Vec<&StatsRoa>> = BTreeMap::new();
+        for roa in roas {
+            if !roa
+                .ranges
+                .iter()
+                .any(|(first, last)| first <= date && date <= last)
+            {
+                continue;
+            }
+            let family = match roa.net {
+                IpNet::V4(_) => "ipv4",
+                IpNet::V6(_) => "ipv6",
+            };
+            groups
+                .entry((roa.tal.as_str(), family))
+                .or_default()
+                .push(roa);
+        }
+
+        for ((tal, family), group) in groups {
+            let asns: HashSet<u32> = group.iter().map(|r| r.asn).collect();
+            let prefixes: HashSet<&IpNet> = group.iter().map(|r| &r.net).collect();
+            let (v4_addresses, v6_48s) = address_space(prefixes.iter().cloned());
+            stats.push(RoaStats {
+                date: date.to_string(),
+                tal: tal.to_string(),
+                family: family.to_string(),
+                roas: group.len(),
+                asns: asns.len(),
+                prefixes: prefixes.len(),
+                address_space: match family {
+                    "ipv4" => v4_addresses as f64 / 256.0,
+                    _ => v6_48s,
+                },
+            });
+        }
+    }
+    stats
+}
+
+/// Check that `from` is not later than `to` and that the date range spans at most
+/// [MAX_STATS_SPAN_DAYS] days.
+fn check_stats_range(from: NaiveDate, to: NaiveDate) -> Result<(), ApiError> {
+    if from > to {
+        return Err(ApiError::new_bad_request(
+            "the `from` date must not be later than the `to` date",
+        ));
+    }
+    if (to - from).num_days() + 1 > MAX_STATS_SPAN_DAYS {
+        return Err(ApiError::new_bad_request(format!(
+            "the date range must span at most {} days",
+            MAX_STATS_SPAN_DAYS
+        )));
+    }
+    Ok(())
+}
+
+/// Compute the statistics of all TALs on the sample dates between `from` and `to`, caching them on
+/// first use.
+///
+/// The ROAs valid on any day of the date range are fetched once for all sample dates.
+async fn load_stats(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<RoaStatsSet>,
+    from: NaiveDate,
+    to: NaiveDate,
+    interval: StatsInterval,
+) -> Result<Arc<RoaStatsSet>, ApiError> {
+    let key = format!("{}/{}/{}", from, to, interval.name());
+    cache
+        .get_or_try_insert_with(key.as_str(), || async {
+            let mut params = QueryHistoryParams::new(0, 0);
+            params.date_from = Some(from.to_string());
+            params.date_to = Some(to.to_string());
+            params.date_range_match = Some("any".to_string());
+            let entries = query_history_all(db, &params).await?;
+
+            tokio::task::spawn_blocking(move || {
+                let roas: Vec<StatsRoa> = entries
+                    .iter()
+                    .filter_map(|entry| {
+                        Some(StatsRoa {
+                            net: IpNet::from_str(entry.prefix.as_str()).ok()?.trunc(),
+                            asn: entry.asn,
+                            tal: entry.tal.clone(),
+                            ranges: entry.parsed_date_ranges(),
+                        })
+                    })
+                    .collect();
+                RoaStatsSet {
+                    stats: compute_stats(&roas, &interval.sample_dates(from, to)),
+                }
+            })
+            .await
+            .map_err(|_| ApiError::new_internal("cannot compute ROA statistics"))
+        })
+        .await
+}
+
+/// ROA statistics by date, TAL and address family.
+///
+/// Computes the number of ROAs, unique ASNs, unique prefixes and covered address space from the ROA
+/// history, per day, week or month in a date range of at most 366 days.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/roas/stats",
+    responses(
+        (status = 200, description = "ROA statistics", body = RoaStatsResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        RoaStatsQuery,
+    )
+)]
+pub async fn search_roa_stats(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(cache): Extension<Arc<TtlCache<RoaStatsSet>>>,
+    query: Query<RoaStatsQuery>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::parse(&query.format)?;
+    let interval = StatsInterval::parse(&query.interval)?;
+    let from = parse_query_date(query.from.as_str())?;
+    let to = match &query.to {
+        Some(to) => parse_query_date(to)?,
+        None => latest_roa_date(),
+    };
+    check_stats_range(from, to)?;
+    let tals = parse_tals(&query.tal)?;
+
+    let stats = load_stats(&db, &cache, from, to, interval).await?;
+    let data: Vec<RoaStats> = stats
+        .stats
+        .iter()
+        .filter(|stats| tals.is_empty() || tals.contains(&stats.tal))
+        .cloned()
+        .collect();
+
+    if format != OutputFormat::Json {
+        return Ok(rows_response(format, &data));
+    }
+    Ok(Json(RoaStatsResponse {
+        from: from.to_string(),
+        to: to.to_string(),
+        interval: interval.name().to_string(),
+        count: data.len(),
+        data,
+    })
+    .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::date;
+
+    #[test]
+    fn test_sample_dates() {
+        let (from, to) = (date("2023-01-30"), date("2023-03-15"));
+        assert_eq!(StatsInterval::Day.sample_dates(from, to).len(), 45);
+        assert_eq!(
+            StatsInterval::Month.sample_dates(from, to),
+            vec![date("2023-01-31"), date("2023-02-28"), date("2023-03-15")]
+        );
+        // 2023-01-30 is a Monday
+        let weeks = StatsInterval::Week.sample_dates(from, to);
+        assert_eq!(weeks[0], date("2023-02-05"));
+        assert_eq!(weeks.last(), Some(&to));
+    }
+
+    #[test]
+    fn test_check_stats_range() {
+        let from = date("2024-01-01");
+        assert!(check_stats_range(from, from).is_ok());
+        // 2024 is a leap year with 366 days
+        assert!(check_stats_range(from, date("2024-12-31")).is_ok());
+        assert!(check_stats_range(from, date("2025-01-01")).is_err());
+        assert!(check_stats_range(from, date("2023-12-31")).is_err());
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let roa = |prefix: &str, asn: u32, first: &str, last: &str| StatsRoa {
+            net: IpNet::from_str(prefix).unwrap(),
+            asn,
+            tal: "apnic".to_string(),
+            ranges: vec![(date(first), date(last))],
+        };
+        let roas = vec![
+            roa("1.1.0.0/16", 13335, "2023-01-01", "2023-02-01"),
+            // covered by 1.1.0.0/16
+            roa("1.1.1.0/24", 13335, "2023-01-01", "2023-02-01"),
+            roa("1.1.1.0/24", 64500, "2023-01-01", "2023-02-01"),
+            roa("2001:db8::/32", 64500, "2023-01-15", "2023-02-01"),
+        ];
+        let stats = compute_stats(&roas, &[date("2023-01-10"), date("2023-01-20")]);
+        assert_eq!(stats.len(), 3);
+        assert_eq!(
+            stats[0],
+            RoaStats {
+                date: "2023-01-10".to_string(),
+                tal: "apnic".to_string(),
+                family: "ipv4".to_string(),
+                roas: 3,
+                asns: 2,
+                prefixes: 2,
+                address_space: 256.0,
+            }
+        );
+        assert_eq!(stats[2].family, "ipv6");
+        assert_eq!(stats[2].address_space, 65536.0);
+    }
+}
@@ -0,0 +1,430 @@
+use crate::api::{
+    latest_roa_date, merge_date_ranges, parse_match_mode, parse_tals, query_history_bounded,
+    ApiError, Pagination, QueryHistoryParams, RoasRawEntry, DEFAULT_MERGE_GAPS,
+};
+use crate::db::BgpkitDatabase;
+use axum::extract::Query;
+use axum::http::{header, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RoaEvent {
+    /// date of the event: the first valid day for `created`, `re-created` and `max_len_changed`
+    /// events, the first day no longer valid for `revoked` events
+    date: String,
+
+    /// type of event: `created`, `revoked`, `re-created` or `max_len_changed`
+    event: String,
+
+    /// prefix
+    prefix: String,
+
+    /// Autonomous system (AS) number
+    asn: u32,
+
+    /// trust anchor locator
+    tal: String,
+
+    /// maximum prefix length of the ROA
+    max_len: u32,
+
+    /// maximum prefix length before the change, only set for `max_len_changed` events
+    previous_max_len: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RoaTimelineResponse {
+    page: usize,
+    page_size: usize,
+
+    /// total number of events
+    total: usize,
+    count: usize,
+    data: Vec<RoaEvent>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RoaTimelineQuery {
+    /// ASN to show ROA events for
+    asn: Option<u32>,
+
+    /// IP prefix to show ROA events for, e.g. `?prefix=1.1.1.0/24`
+    prefix: Option<String>,
+
+    /// how ROAs are matched against `prefix`: `covered` (default) for ROAs of the prefix or more
+    /// specifics, `exact`, `covering` or `valid`
+    #[serde(rename = "match")]
+    match_mode: Option<String>,
+
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// output format: `json` (default), `atom` or `rss`. feeds list the most recent events first
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimelineFormat {
+    Json,
+    Atom,
+    Rss,
+}
+
+impl TimelineFormat {
+    fn parse(format: &Option<String>) -> Result<Self, ApiError> {
+        match format.as_deref().map(|f| f.to_lowercase()) {
+            None => Ok(TimelineFormat::Json),
+            Some(f) => match f.as_str() {
+                "json" => Ok(TimelineFormat::Json),
+                "atom" => Ok(TimelineFormat::Atom),
+                "rss" => Ok(TimelineFormat::Rss),
+                _ => Err(ApiError::new_bad_request(format!(
+                    "unknown output format: {}, valid values are `json`, `atom` and `rss`",
+                    f
+                ))),
+            },
+        }
+    }
+}
+
+/// date, whether the range starts or ends on it, and max length of a valid date range boundary
+type Boundary = (NaiveDate, bool, u32);
+
+/// Derive ROA events from the valid date ranges of ROAs.
+///
+/// Ranges are merged over gaps of [DEFAULT_MERGE_GAPS] days. A ROA revoked and another created on
+/// the same day for the same prefix, ASN and TAL is reported as a max length change. ROAs still
+/// valid on `latest` are not revoked. Events are sorted by date.
+fn roa_events(entries: &[RoasRawEntry], latest: NaiveDate) -> Vec<RoaEvent> {
+    // range boundaries grouped by prefix, ASN and TAL
+    let mut groups: BTreeMap<(String, u32, String), Vec<Boundary>> = BTreeMap::new();
+    for entry in entries {
+        let (ranges, _) = merge_date_ranges(entry.parsed_date_ranges(), DEFAULT_MERGE_GAPS);
+        let boundaries = groups
+            .entry((entry.prefix.clone(), entry.asn, entry.tal.clone()))
+            .or_default();
+        for (first, last) in ranges {
+            boundaries.push((first, true, entry.max_len));
+            if last < latest {
+                boundaries.push((last + Duration::days(1), false, entry.max_len));
+            }
+        }
+    }
+
+    let mut events = vec![];
+    for ((prefix, asn, tal), boundaries) in groups {
+        let event = |date: NaiveDate, event: &str, max_len: u32, previous_max_len| RoaEvent {
+            date: date.to_string(),
+            event: event.to_string(),
+            prefix: prefix.clone(),
+            asn,
+            tal: tal.clone(),
+            max_len,
+            previous_max_len,
+        };
+
+        // boundaries of each day, with starts and ends of the same day paired as max_len changes
+        let mut by_date: BTreeMap<NaiveDate, (Vec<u32>, Vec<u32>)> = BTreeMap::new();
+        for (date, is_start, max_len) in boundaries {
+            let (starts, ends) = by_date.entry(date).or_default();
+            match is_start {
+                true => starts.push(max_len),
+                false => ends.push(max_len),
+            }
+        }
+
+        let mut seen = false;
+        for (date, (mut starts, mut ends)) in by_date {
+            // the same max length ending and starting on one day is a merged gap, not an event
+            starts.retain(|s| match ends.iter().position(|e| e == s) {
+                Some(pos) => {
+                    ends.remove(pos);
+                    false
+                }
+                None => true,
+            });
+            starts.sort();
+            ends.sort();
+            let paired = starts.len().min(ends.len());
+            for (new, old) in starts.iter().zip(ends.iter()) {
+                events.push(event(date, "max_len_changed", *new, Some(*old)));
+            }
+            for max_len in ends.iter().skip(paired) {
+                events.push(event(date, "revoked", *max_len, None));
+            }
+            for max_len in starts.iter().skip(paired) {
+                let kind = match seen {
+                    true => "re-created",
+                    false => "created",
+                };
+                events.push(event(date, kind, *max_len, None));
+            }
+            seen = true;
+        }
+    }
+    events.sort_by(|a, b| {
+        (&a.date, &a.prefix, a.asn, &a.tal).cmp(&(&b.date, &b.prefix, b.asn, &b.tal))
+    });
+    events
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn event_title(event: &RoaEvent) -> String {
+    match &event.previous_max_len {
+        Some(previous) => format!(
+            "ROA {} AS{} max_len changed from {} to {}",
+            event.prefix, event.asn, previous, event.max_len
+        ),
+        None => format!(
+            "ROA {} AS{} max_len {} {}",
+            event.prefix, event.asn, event.max_len, event.event
+        ),
+    }
+}
+
+fn event_id(event: &RoaEvent) -> String {
+    format!(
+        "tag:bgpkit.com,2023:roa/{}/{}/{}/{}/{}/{}",
+        event.tal, event.prefix, event.asn, event.max_len, event.event, event.date
+    )
+}
+
+/// Render events as an Atom feed, `events` being sorted most recent first.
+fn to_atom(title: &str, link: &str, events: &[RoaEvent]) -> String {
+    let updated = events
+        .first()
+        .map(|e| e.date.clone())
+        .unwrap_or_else(|| latest_roa_date().to_string());
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <title>{}</title>\n<id>{}</id>\n<link rel=\"self\" href=\"{}\"/>\n\
+         <updated>{}T00:00:00Z</updated>\n<author><name>BGPKIT</name></author>\n",
+        xml_escape(title),
+        xml_escape(link),
+        xml_escape(link),
+        updated
+    );
+    for event in events {
+        feed.push_str(&format!(
+            "<entry>\n<title>{}</title>\n<id>{}</id>\n<updated>{}T00:00:00Z</updated>\n\
+             <summary>{} TAL on {}</summary>\n</entry>\n",
+            xml_escape(&event_title(event)),
+            xml_escape(&event_id(event)),
+            event.date,
+            xml_escape(&event.tal),
+            event.date
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+/// RFC 2822 date of a YYYY-MM-DD date
+fn rss_date(date: &str) -> String {
+    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(d) => d.format("%a, %d %b %Y 00:00:00 +0000").to_string(),
+        Err(_) => date.to_string(),
+    }
+}
+
+/// Render events as an RSS 2.0 feed, `events` being sorted most recent first.
+fn to_rss(title: &str, link: &str, events: &[RoaEvent]) -> String {
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\">\n<channel>\n\
+         <title>{}</title>\n<link>{}</link>\n<description>{}</description>\n",
+        xml_escape(title),
+        xml_escape(link),
+        xml_escape(title)
+    );
+    for event in events {
+        feed.push_str(&format!(
+            "<item>\n<title>{}</title>\n<guid isPermaLink=\"false\">{}</guid>\n\
+             <pubDate>{}</pubDate>\n<description>{} TAL on {}</description>\n</item>\n",
+            xml_escape(&event_title(event)),
+            xml_escape(&event_id(event)),
+            rss_date(&event.date),
+            xml_escape(&event.tal),
+            event.date
+        ));
+    }
+    feed.push_str("</channel>\n</rss>\n");
+    feed
+}
+
+/// maximum number of ROA entries a timeline is built from
+const MAX_TIMELINE_ROAS: usize = 10_000;
+
+/// default public base URL of the API, used for links in feeds
+const DEFAULT_API_BASE_URL: &str = "https://api.bgpkit.com";
+
+/// Public base URL of the API, from `BGPKIT_API_BASE_URL` or the default one.
+fn api_base_url() -> &'static str {
+    static BASE_URL: OnceLock<String> = OnceLock::new();
+    BASE_URL.get_or_init(|| {
+        std::env::var("BGPKIT_API_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_API_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string()
+    })
+}
+
+/// Link of a feed requested with `uri`, under the public base URL of the API.
+fn feed_link(base_url: &str, uri: &Uri) -> String {
+    let path = uri.path_and_query().map_or(uri.path(), |p| p.as_str());
+    format!("{}{}", base_url, path)
+}
+
+/// Timeline of ROA events for an ASN or prefix.
+///
+/// Lists when ROAs were created, revoked, re-created, or had their max length changed, derived from
+/// the valid date ranges of the ROAs. Events are sorted by date and paginated. Use `format=atom` or
+/// `format=rss` to subscribe to changes as a feed, linking to `BGPKIT_API_BASE_URL`.
+///
+/// Queries matching more than 10,000 ROA entries are rejected, narrow them down with a more
+/// specific `prefix` or `match`, or with `asn` or `tal`.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/roas/timeline",
+    responses(
+        (status = 200, description = "ROA events sorted by date", body = RoaTimelineResponse),
+        (status = 400, description = "invalid query parameters or too many matching ROAs"),
+    ),
+    params(
+        RoaTimelineQuery,
+        Pagination,
+    )
+)]
+pub async fn search_roa_timeline(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    uri: Uri,
+    query: Query<RoaTimelineQuery>,
+    pagination: Query<Pagination>,
+) -> Result<Response, ApiError> {
+    let format = TimelineFormat::parse(&query.format)?;
+    let (page, page_size) = pagination.extract(1000);
+
+    let mut params = QueryHistoryParams::new(0, 0);
+    let mut subject = vec![];
+    match (&query.asn, &query.prefix) {
+        (None, None) => {
+            return Err(ApiError::new_bad_request(
+                "either `asn` or `prefix` must be specified",
+            ))
+        }
+        (asn, prefix) => {
+            if let Some(asn) = asn {
+                params.asn = *asn as i64;
+                subject.push(format!("AS{}", asn));
+            }
+            if let Some(prefix) = prefix {
+                params.prefix = prefix.clone();
+                params.match_mode = Some(
+                    parse_match_mode(&query.match_mode)?.unwrap_or_else(|| "covered".to_string()),
+                );
+                subject.push(prefix.clone());
+            }
+        }
+    }
+    params.set_tals(parse_tals(&query.tal)?);
+
+    let entries = query_history_bounded(&db, &params, MAX_TIMELINE_ROAS).await?;
+    let mut events = roa_events(&entries, latest_roa_date());
+    if format != TimelineFormat::Json {
+        events.reverse();
+    }
+    let total = events.len();
+    let data: Vec<RoaEvent> = events
+        .into_iter()
+        .skip(page * page_size)
+        .take(page_size)
+        .collect();
+
+    let title = format!("ROA changes for {}", subject.join(" "));
+    let link = feed_link(api_base_url(), &uri);
+    match format {
+        TimelineFormat::Atom => Ok((
+            [(header::CONTENT_TYPE, "application/atom+xml")],
+            to_atom(&title, &link, &data),
+        )
+            .into_response()),
+        TimelineFormat::Rss => Ok((
+            [(header::CONTENT_TYPE, "application/rss+xml")],
+            to_rss(&title, &link, &data),
+        )
+            .into_response()),
+        TimelineFormat::Json => Ok(Json(RoaTimelineResponse {
+            page,
+            page_size,
+            total,
+            count: data.len(),
+            data,
+        })
+        .into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::roa;
+
+    #[test]
+    fn test_roa_events() {
+        let entries = vec![
+            // created, revoked, re-created and still valid
+            roa(
+                "10.0.0.0/24",
+                64500,
+                24,
+                &["[2023-01-01,2023-02-01)", "[2023-03-01,2023-06-01)"],
+            ),
+            // max_len changed from 24 to 22
+            roa("10.1.0.0/22", 64500, 24, &["[2023-01-15,2023-02-15)"]),
+            roa("10.1.0.0/22", 64500, 22, &["[2023-02-15,2023-06-01)"]),
+        ];
+        let latest = NaiveDate::from_ymd_opt(2023, 5, 31).unwrap();
+        let events: Vec<(String, String, u32)> = roa_events(&entries, latest)
+            .into_iter()
+            .map(|e| (e.date, e.event, e.max_len))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("2023-01-01".to_string(), "created".to_string(), 24),
+                ("2023-01-15".to_string(), "created".to_string(), 24),
+                ("2023-02-01".to_string(), "revoked".to_string(), 24),
+                ("2023-02-15".to_string(), "max_len_changed".to_string(), 22),
+                ("2023-03-01".to_string(), "re-created".to_string(), 24),
+            ]
+        );
+
+        let event = roa_events(&entries, latest).remove(3);
+        assert_eq!(event.previous_max_len, Some(24));
+        let atom = to_atom("test", "https://localhost/roas/timeline", &[event]);
+        assert!(
+            atom.contains("<title>ROA 10.1.0.0/22 AS64500 max_len changed from 24 to 22</title>")
+        );
+        assert_eq!(rss_date("2023-02-15"), "Wed, 15 Feb 2023 00:00:00 +0000");
+
+        let uri: Uri = "/roas/timeline?asn=64500&format=atom".parse().unwrap();
+        assert_eq!(
+            feed_link("https://api.example.com", &uri),
+            "https://api.example.com/roas/timeline?asn=64500&format=atom"
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use crate::api::{
+    load_vrp_set, parse_as_of, parse_asn, query_history, ApiError, QueryHistoryParams, RoasEntry,
+    RoasRawEntry, RovReason, RovState, Vrp, VrpSet, DEFAULT_MERGE_GAPS,
+};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::extract::Query;
+use axum::{Extension, Json};
+use chrono::NaiveDate;
+use futures::{stream, StreamExt, TryStreamExt};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// maximum number of ROAs fetched for a single covering prefix
+const MAX_COVERING_ROAS: usize = 1000;
+
+/// number of covering prefixes looked up at once
+const COVERING_LOOKUP_CONCURRENCY: usize = 4;
+
+/// maximum number of routes accepted by a single bulk validation request
+const BULK_MAX_ROUTES: usize = 1_000_000;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RovResponse {
+    /// validated prefix
+    prefix: String,
+
+    /// validated origin AS number
+    asn: u32,
+
+    /// date of the ROA set used for validation
+    date: String,
+
+    /// validation state: `valid`, `invalid` or `not-found`
+    state: RovState,
+
+    /// reason for an `invalid` state: `origin_mismatch` or `max_len_exceeded`
+    reason: Option<RovReason>,
+
+    /// ROAs matching the route, i.e. covering the prefix with the same origin and a sufficient max
+    /// length
+    matching: Vec<RoasEntry>,
+
+    /// all ROAs covering the prefix, including the matching ones
+    covering: Vec<RoasEntry>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RovQuery {
+    /// IP prefix of the route, e.g. `1.1.1.0/24`
+    prefix: String,
+
+    /// origin AS number of the route
+    asn: u32,
+
+    /// date of the ROA set to validate against, format: YYYY-MM-DD. defaults to the previous day
+    /// UTC
+    date: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RovRoute {
+    /// IP prefix of the route
+    prefix: String,
+
+    /// origin AS number of the route
+    asn: u32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RovBulkResult {
+    /// validated prefix
+    prefix: String,
+
+    /// validated origin AS number
+    asn: u32,
+
+    /// validation state: `valid`, `invalid` or `not-found`
+    state: RovState,
+
+    /// reason for an `invalid` state: `origin_mismatch` or `max_len_exceeded`
+    reason: Option<RovReason>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, ToSchema)]
+pub struct RovCounts {
+    valid: usize,
+    invalid: usize,
+    not_found: usize,
+
+    /// number of invalid routes due to origin mismatch
+    origin_mismatch: usize,
+
+    /// number of invalid routes due to prefixes longer than the ROA max length
+    max_len_exceeded: usize,
+}
+
+impl RovCounts {
+    pub(crate) fn add(&mut self, state: RovState, reason: Option<RovReason>) {
+        match state {
+            RovState::Valid => self.valid += 1,
+            RovState::Invalid => self.invalid += 1,
+            RovState::NotFound => self.not_found += 1,
+        }
+        match reason {
+            Some(RovReason::OriginMismatch) => self.origin_mismatch += 1,
+            Some(RovReason::MaxLenExceeded) => self.max_len_exceeded += 1,
+            None => {}
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RovBulkResponse {
+    /// date of the ROA set used for validation
+    date: String,
+
+    /// number of routes validated
+    count: usize,
+
+    /// number of routes in each validation state
+    counts: RovCounts,
+
+    /// validation results, in the same order as the input routes
+    data: Vec<RovBulkResult>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RovBulkQuery {
+    /// date of the ROA set to validate against, format: YYYY-MM-DD. defaults to the previous day
+    /// UTC
+    date: Option<String>,
+}
+
+/// Parse a list of routes from either a JSON array of `{"prefix", "asn"}` objects or CSV lines of
+/// `prefix,asn`.
+///
+/// In the CSV form, empty lines, lines starting with `#` and a `prefix,asn` header are skipped, and
+/// an optional `AS` prefix on the ASN is accepted.
+fn parse_routes(body: &str) -> Result<Vec<(IpNet, u32)>, ApiError> {
+    let body = body.trim();
+    if body.starts_with('[') {
+        let routes: Vec<RovRoute> = match serde_json::from_str(body) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(ApiError::new_bad_request(format!(
+                    "cannot parse JSON array of routes: {}",
+                    e
+                )))
+            }
+        };
+        return routes
+            .into_iter()
+            .map(|route| Ok((parse_prefix(route.prefix.as_str())?, route.asn)))
+            .collect();
+    }
+
+    let mut routes = vec![];
+    for (i, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.to_lowercase() == "prefix,asn" {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() != 2 {
+            return Err(ApiError::new_bad_request(format!(
+                "line {}: expected `prefix,asn`, got: {}",
+                i + 1,
+                line
+            )));
+        }
+        let prefix = parse_prefix(fields[0])?;
+        let asn = match parse_asn(fields[1]) {
+            Some(asn) => asn,
+            None => {
+                return Err(ApiError::new_bad_request(format!(
+                    "line {}: cannot parse ASN: {}",
+                    i + 1,
+                    fields[1]
+                )))
+            }
+        };
+        routes.push((prefix, asn));
+    }
+    Ok(routes)
+}
+
+/// Parse an IP prefix from a query parameter.
+pub(crate) fn parse_prefix(prefix: &str) -> Result<IpNet, ApiError> {
+    match IpNet::from_str(prefix.trim()) {
+        Ok(net) => Ok(net.trunc()),
+        Err(_) => Err(ApiError::new_bad_request(format!(
+            "cannot parse IP prefix: {}",
+            prefix
+        ))),
+    }
+}
+
+/// Parse an optional VRP date from a query parameter, defaulting to the latest ROA data.
+pub(crate) fn parse_vrp_date(date: &Option<String>) -> Result<NaiveDate, ApiError> {
+    parse_as_of(date)
+}
+
+/// RPKI route origin validation of a single route.
+///
+/// Validates the route per RFC 6811 against the ROAs valid on the given date, and returns the
+/// validation state, the reason for invalid routes, and the matching and covering ROAs.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/rov",
+    responses(
+        (status = 200, description = "route origin validation result", body = RovResponse),
+        (status = 400, description = "invalid prefix or date"),
+    ),
+    params(
+        RovQuery,
+    )
+)]
+pub async fn validate_rov(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(vrp_cache): Extension<Arc<TtlCache<VrpSet>>>,
+    query: Query<RovQuery>,
+) -> Result<Json<RovResponse>, ApiError> {
+    let prefix = parse_prefix(query.prefix.as_str())?;
+    let vrp_set = load_vrp_set(&db, &vrp_cache, parse_vrp_date(&query.date)?).await?;
+    let date = vrp_set.date;
+    let outcome = vrp_set.validate(&prefix, query.asn);
+
+    // the ROAs behind the covering VRPs, looked up by their exact prefixes
+    let mut covering_prefixes: Vec<IpNet> = outcome.covering.iter().map(|vrp| vrp.net).collect();
+    covering_prefixes.sort();
+    covering_prefixes.dedup();
+    let lookups: Vec<_> = covering_prefixes
+        .into_iter()
+        .map(|net| fetch_exact_roas(&db, net, date))
+        .collect();
+    let entries: Vec<Vec<RoasRawEntry>> = stream::iter(lookups)
+        .buffer_unordered(COVERING_LOOKUP_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    let mut matching = vec![];
+    let mut covering = vec![];
+    for entry in entries.into_iter().flatten() {
+        let vrp = match Vrp::from_raw(&entry) {
+            Some(vrp) => vrp,
+            None => continue,
+        };
+        if !outcome.covering.contains(&&vrp) {
+            continue;
+        }
+        let roa = entry.into_roas_entry(DEFAULT_MERGE_GAPS, date);
+        if outcome.matching.contains(&&vrp) {
+            matching.push(roa.clone());
+        }
+        covering.push(roa);
+    }
+
+    Ok(Json(RovResponse {
+        prefix: prefix.to_string(),
+        asn: query.asn,
+        date: date.to_string(),
+        state: outcome.state,
+        reason: outcome.reason,
+        matching,
+        covering,
+    }))
+}
+
+/// ROAs of exactly `net` valid on `date`.
+async fn fetch_exact_roas(
+    db: &Arc<BgpkitDatabase>,
+    net: IpNet,
+    date: NaiveDate,
+) -> Result<Vec<RoasRawEntry>, ApiError> {
+    let mut params = QueryHistoryParams::new(MAX_COVERING_ROAS, 0);
+    params.prefix = net.to_string();
+    params.date = date.to_string();
+    // ROAs validly covering the prefix include all ROAs of the prefix itself, plus less specific
+    // ones with a sufficient max length that are looked up separately
+    Ok(query_history(db, &params)
+        .await?
+        .into_iter()
+        .filter(|entry| IpNet::from_str(entry.prefix.as_str()).is_ok_and(|n| n.trunc() == net))
+        .collect())
+}
+
+/// Bulk RPKI route origin validation of a list of routes.
+///
+/// The request body is either a JSON array of `{"prefix": "1.1.1.0/24", "asn": 13335}` objects or
+/// CSV lines of `prefix,asn`, up to 1,000,000 routes per request. All routes are validated against
+/// the full set of ROAs valid on the given date.
+#[utoipa::path(
+    post,
+    tag = "bgp",
+    path = "/rov/bulk",
+    request_body(content = String, description = "JSON array of `{prefix, asn}` objects or CSV lines of `prefix,asn`"),
+    responses(
+        (status = 200, description = "route origin validation results", body = RovBulkResponse),
+        (status = 400, description = "malformed route list or date"),
+    ),
+    params(
+        RovBulkQuery,
+    )
+)]
+pub async fn bulk_validate_rov(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(vrp_cache): Extension<Arc<TtlCache<VrpSet>>>,
+    query: Query<RovBulkQuery>,
+    body: String,
+) -> Result<Json<RovBulkResponse>, ApiError> {
+    let routes = parse_routes(body.as_str())?;
+    if routes.len() > BULK_MAX_ROUTES {
+        return Err(ApiError::new_bad_request(format!(
+            "too many routes in request: {} (max {})",
+            routes.len(),
+            BULK_MAX_ROUTES
+        )));
+    }
+    let vrp_set = load_vrp_set(&db, &vrp_cache, parse_vrp_date(&query.date)?).await?;
+    let date = vrp_set.date;
+
+    let result = tokio::task::spawn_blocking(move || {
+        let mut counts = RovCounts::default();
+        let data: Vec<RovBulkResult> = routes
+            .into_iter()
+            .map(|(prefix, asn)| {
+                let outcome = vrp_set.validate(&prefix, asn);
+                counts.add(outcome.state, outcome.reason);
+                RovBulkResult {
+                    prefix: prefix.to_string(),
+                    asn,
+                    state: outcome.state,
+                    reason: outcome.reason,
+                }
+            })
+            .collect();
+        (counts, data)
+    })
+    .await;
+    let (counts, data) = match result {
+        Ok(r) => r,
+        Err(_) => return Err(ApiError::new_internal("route validation failed")),
+    };
+
+    Ok(Json(RovBulkResponse {
+        date: date.to_string(),
+        count: data.len(),
+        counts,
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_routes() {
+        let routes =
+            parse_routes(r#"[{"prefix": "1.1.1.0/24", "asn": 13335}, {"prefix": "2001:db8::/32", "asn": 64500}]"#)
+                .unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[1].1, 64500);
+
+        let routes =
+            parse_routes("prefix,asn\n# comment\n1.1.1.0/24,13335\n\n8.8.8.0/24, AS15169\n")
+                .unwrap();
+        assert_eq!(
+            routes,
+            vec![
+                (IpNet::from_str("1.1.1.0/24").unwrap(), 13335),
+                (IpNet::from_str("8.8.8.0/24").unwrap(), 15169)
+            ]
+        );
+
+        assert!(parse_routes("1.1.1.0/24").is_err());
+        assert!(parse_routes("1.1.1.0/33,13335").is_err());
+
+        // the `AS` prefix is stripped case-insensitively, once
+        assert_eq!(parse_routes("1.1.1.0/24,aS13335").unwrap()[0].1, 13335);
+        assert!(parse_routes("1.1.1.0/24,ASas13335").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::api::{
+    fetch_pfx2as, load_vrp_set, parse_asn_list, parse_tals, parse_vrp_date, rows_response,
+    ApiError, CsvRow, OutputFormat, Pagination, Pfx2AsEntry, RovReason, RovState, RpkiCoveringVrp,
+    VrpSet,
+};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RovInvalid {
+    /// announced prefix
+    prefix: String,
+
+    /// origin AS number of the announcement
+    asn: u32,
+
+    /// `origin_mismatch` if no ROA authorizes the origin AS, `max_len_exceeded` if the prefix is
+    /// more specific than allowed by the ROAs of the origin AS
+    reason: RovReason,
+
+    /// ROAs covering the prefix that invalidate the announcement
+    roas: Vec<RpkiCoveringVrp>,
+}
+
+impl CsvRow for RovInvalid {
+    const COLUMNS: &'static [&'static str] = &["prefix", "asn", "reason", "roas"];
+}
+
+/// All RPKI-invalid announcements of a date.
+pub struct RovInvalidSet {
+    date: NaiveDate,
+    invalids: Vec<RovInvalid>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RovInvalidsResponse {
+    /// date of the VRP set
+    date: String,
+    page: usize,
+    page_size: usize,
+
+    /// total number of invalid announcements matching the filters
+    total: usize,
+    count: usize,
+    data: Vec<RovInvalid>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RovInvalidsQuery {
+    /// date of the announcements and ROAs, format: YYYY-MM-DD. defaults to the latest
+    /// prefix-to-origin data and the VRPs of the previous day UTC
+    date: Option<String>,
+
+    /// filter results by origin ASN, use comma to separate multiple ASNs
+    asn: Option<String>,
+
+    /// filter results to announcements invalidated by ROAs of these trust anchors, e.g. `ripencc`
+    /// or `ripe`. use comma to separate multiple TALs, see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// output format: `json` (default), `csv` or `ndjson`. CSV and NDJSON output contain all matching
+    /// announcements, ignoring pagination
+    format: Option<String>,
+}
+
+/// Validate announcements against a VRP set, keeping the invalid ones.
+fn find_invalids(vrp_set: &VrpSet, announcements: &[Pfx2AsEntry]) -> Vec<RovInvalid> {
+    announcements
+        .iter()
+        .filter_map(|entry| {
+            let net = entry.net()?;
+            let outcome = vrp_set.validate(&net, entry.asn);
+            if outcome.state != RovState::Invalid {
+                return None;
+            }
+            Some(RovInvalid {
+                prefix: net.to_string(),
+                asn: entry.asn,
+                reason: outcome.reason?,
+                roas: outcome
+                    .covering
+                    .iter()
+                    .map(|vrp| RpkiCoveringVrp::from_vrp(vrp))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Load all RPKI-invalid announcements of a date, computing and caching them on first use.
+///
+/// Without `pfx2as_date`, the latest prefix-to-origin data is used.
+async fn load_invalid_set(
+    db: &Arc<BgpkitDatabase>,
+    vrp_cache: &TtlCache<VrpSet>,
+    cache: &TtlCache<RovInvalidSet>,
+    date: NaiveDate,
+    pfx2as_date: Option<NaiveDate>,
+) -> Result<Arc<RovInvalidSet>, ApiError> {
+    let key = match pfx2as_date {
+        Some(d) => format!("{}/{}", date, d),
+        None => format!("{}/latest", date),
+    };
+    cache
+        .get_or_try_insert_with(key.as_str(), || async {
+            let vrp_set = load_vrp_set(db, vrp_cache, date).await?;
+            let announcements = fetch_pfx2as(db, pfx2as_date, None).await?;
+            let invalids =
+                tokio::task::spawn_blocking(move || find_invalids(&vrp_set, &announcements))
+                    .await
+                    .map_err(|_| ApiError::new_internal("validating announcements failed"))?;
+            Ok(RovInvalidSet { date, invalids })
+        })
+        .await
+}
+
+/// RPKI-invalid announcements.
+///
+/// Lists every announcement of the prefix-to-origin dataset that is RPKI-invalid against the VRPs
+/// of the given date, along with the reason and the ROAs invalidating it.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/rov/invalids",
+    responses(
+        (status = 200, description = "RPKI-invalid announcements", body = RovInvalidsResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        RovInvalidsQuery,
+        Pagination,
+    )
+)]
+pub async fn search_rov_invalids(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(vrp_cache): Extension<Arc<TtlCache<VrpSet>>>,
+    Extension(invalid_cache): Extension<Arc<TtlCache<RovInvalidSet>>>,
+    query: Query<RovInvalidsQuery>,
+    pagination: Query<Pagination>,
+) -> Result<Response, ApiError> {
+    let format = OutputFormat::parse(&query.format)?;
+    let (page, page_size) = pagination.extract(10_000);
+    let asns = parse_asn_list(query.asn.as_deref().unwrap_or_default())?;
+    let tals = parse_tals(&query.tal)?;
+    let date = parse_vrp_date(&query.date)?;
+    let pfx2as_date = query.date.as_ref().map(|_| date);
+
+    let set = load_invalid_set(&db, &vrp_cache, &invalid_cache, date, pfx2as_date).await?;
+    let matching = set.invalids.iter().filter(|invalid| {
+        (asns.is_empty() || asns.contains(&invalid.asn))
+            && (tals.is_empty()
+                || invalid
+                    .roas
+                    .iter()
+                    .any(|roa| tals.contains(&roa.tal.to_lowercase())))
+    });
+
+    if format != OutputFormat::Json {
+        let rows: Vec<&RovInvalid> = matching.collect();
+        return Ok(rows_response(format, &rows));
+    }
+
+    let matching: Vec<&RovInvalid> = matching.collect();
+    let data: Vec<RovInvalid> = matching
+        .iter()
+        .skip(page * page_size)
+        .take(page_size)
+        .map(|invalid| (*invalid).clone())
+        .collect();
+    Ok(Json(RovInvalidsResponse {
+        date: set.date.to_string(),
+        page,
+        page_size,
+        total: matching.len(),
+        count: data.len(),
+        data,
+    })
+    .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::vrp_set;
+
+    fn announcement(prefix: &str, asn: u32) -> Pfx2AsEntry {
+        Pfx2AsEntry {
+            prefix: prefix.to_string(),
+            asn,
+        }
+    }
+
+    #[test]
+    fn test_find_invalids_overlapping_vrps() {
+        // a route is only invalid if none of the covering VRPs matches it
+        let set = vrp_set(&[("1.0.0.0/8", 8, 64500), ("1.1.0.0/16", 24, 64500)]);
+        let invalids = find_invalids(
+            &set,
+            &[
+                announcement("1.1.1.0/24", 64500),
+                announcement("1.2.0.0/16", 64500),
+                announcement("1.1.1.0/24", 64501),
+                // unparsable prefixes are skipped
+                announcement("1.1.1.0/33", 64501),
+            ],
+        );
+        assert_eq!(invalids.len(), 2);
+        assert_eq!(invalids[0].prefix, "1.2.0.0/16");
+        assert_eq!(invalids[0].reason, RovReason::MaxLenExceeded);
+        assert_eq!(invalids[0].roas.len(), 1);
+        assert_eq!(invalids[1].reason, RovReason::OriginMismatch);
+        assert_eq!(invalids[1].roas.len(), 2);
+
+        assert!(find_invalids(&set, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_find_invalids_as0_default_route() {
+        // an AS0 ROA for 0.0.0.0/0 invalidates every IPv4 route without a matching VRP
+        let set = vrp_set(&[("0.0.0.0/0", 32, 0), ("8.8.8.0/24", 24, 15169)]);
+        let invalids = find_invalids(
+            &set,
+            &[
+                announcement("0.0.0.0/0", 0),
+                announcement("8.8.8.0/24", 15169),
+                announcement("9.9.9.0/24", 19281),
+                announcement("2001:db8::/32", 64500),
+            ],
+        );
+        let invalid: Vec<(&str, u32)> = invalids
+            .iter()
+            .map(|i| (i.prefix.as_str(), i.asn))
+            .collect();
+        assert_eq!(invalid, vec![("0.0.0.0/0", 0), ("9.9.9.0/24", 19281)]);
+        assert!(invalids
+            .iter()
+            .all(|i| i.reason == RovReason::OriginMismatch));
+    }
+}
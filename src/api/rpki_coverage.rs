@@ -0,0 +1,255 @@
+use crate::api::{
+    address_space, fetch_pfx2as, load_vrp_set, parse_vrp_date, ApiError, RovCounts, RovReason,
+    RovState, Vrp, VrpSet,
+};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::extract::{Path, Query};
+use axum::{Extension, Json};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RpkiCoveringVrp {
+    pub prefix: String,
+    pub max_len: u8,
+    pub asn: u32,
+    pub tal: String,
+}
+
+impl RpkiCoveringVrp {
+    pub(crate) fn from_vrp(vrp: &Vrp) -> Self {
+        RpkiCoveringVrp {
+            prefix: vrp.net.to_string(),
+            max_len: vrp.max_len,
+            asn: vrp.asn,
+            tal: vrp.tal.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RpkiPrefixCoverage {
+    /// announced prefix
+    prefix: String,
+
+    /// validation state: `valid`, `invalid` or `not-found`
+    state: RovState,
+
+    /// reason for an `invalid` state: `origin_mismatch` or `max_len_exceeded`
+    reason: Option<RovReason>,
+
+    /// VRPs covering the prefix
+    covering: Vec<RpkiCoveringVrp>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, ToSchema)]
+pub struct RpkiCoverageTotals {
+    /// address family, `ipv4` or `ipv6`
+    family: String,
+
+    /// number of announced prefixes
+    prefixes: usize,
+
+    #[serde(flatten)]
+    counts: RovCounts,
+
+    /// announced address space with overlapping prefixes counted once, in /24 equivalents for IPv4
+    /// and /48 equivalents for IPv6
+    address_space: f64,
+
+    /// address space of RPKI-valid prefixes, in the same unit as `address_space`
+    valid_address_space: f64,
+
+    /// fraction of the announced address space covered by RPKI-valid prefixes
+    valid_fraction: f64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RpkiCoverageResponse {
+    asn: u32,
+
+    /// date of the VRP set
+    date: String,
+
+    totals: Vec<RpkiCoverageTotals>,
+    prefixes: Vec<RpkiPrefixCoverage>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct RpkiCoverageQuery {
+    /// date of the announced prefixes and ROAs, format: YYYY-MM-DD. defaults to the latest
+    /// prefix-to-origin data and the VRPs of the previous day UTC
+    date: Option<String>,
+}
+
+/// address space of prefixes of one family, in /24 equivalents for IPv4 and /48 for IPv6
+fn family_space(nets: &[IpNet], ipv4: bool) -> f64 {
+    let (v4_addresses, v6_48s) = address_space(nets);
+    match ipv4 {
+        true => v4_addresses as f64 / 256.0,
+        false => v6_48s,
+    }
+}
+
+/// Validate the prefixes originated by `asn` and summarize the coverage by address family.
+fn rpki_coverage(
+    vrp_set: &VrpSet,
+    asn: u32,
+    nets: &[IpNet],
+) -> (Vec<RpkiCoverageTotals>, Vec<RpkiPrefixCoverage>) {
+    let mut prefixes = vec![];
+    let mut totals = vec![];
+    for (family, ipv4) in [("ipv4", true), ("ipv6", false)] {
+        let family_nets: Vec<IpNet> = nets
+            .iter()
+            .filter(|n| matches!(n, IpNet::V4(_)) == ipv4)
+            .cloned()
+            .collect();
+        if family_nets.is_empty() {
+            continue;
+        }
+
+        let mut counts = RovCounts::default();
+        let mut valid_nets = vec![];
+        for net in &family_nets {
+            let outcome = vrp_set.validate(net, asn);
+            counts.add(outcome.state, outcome.reason);
+            if outcome.state == RovState::Valid {
+                valid_nets.push(*net);
+            }
+            prefixes.push(RpkiPrefixCoverage {
+                prefix: net.to_string(),
+                state: outcome.state,
+                reason: outcome.reason,
+                covering: outcome
+                    .covering
+                    .iter()
+                    .map(|vrp| RpkiCoveringVrp::from_vrp(vrp))
+                    .collect(),
+            });
+        }
+
+        let space = family_space(&family_nets, ipv4);
+        let valid_space = family_space(&valid_nets, ipv4);
+        totals.push(RpkiCoverageTotals {
+            family: family.to_string(),
+            prefixes: family_nets.len(),
+            counts,
+            address_space: space,
+            valid_address_space: valid_space,
+            valid_fraction: match space > 0.0 {
+                true => valid_space / space,
+                false => 0.0,
+            },
+        });
+    }
+    (totals, prefixes)
+}
+
+/// RPKI coverage of the prefixes originated by an autonomous system.
+///
+/// Validates each prefix announced by the AS against the VRPs of the given date, and summarizes how
+/// much of the announced address space is covered by RPKI-valid routes per address family.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/asninfo/{asn}/rpki",
+    responses(
+        (status = 200, description = "RPKI coverage of the announced prefixes", body = RpkiCoverageResponse),
+        (status = 400, description = "invalid query parameters"),
+        (status = 501, description = "required database objects are not installed"),
+    ),
+    params(
+        ("asn" = u32, Path, description = "AS number to report RPKI coverage for"),
+        RpkiCoverageQuery,
+    )
+)]
+pub async fn search_rpki_coverage(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(vrp_cache): Extension<Arc<TtlCache<VrpSet>>>,
+    Path(asn): Path<u32>,
+    query: Query<RpkiCoverageQuery>,
+) -> Result<Json<RpkiCoverageResponse>, ApiError> {
+    let date = parse_vrp_date(&query.date)?;
+    let pfx2as_date = query.date.as_ref().map(|_| date);
+
+    let vrp_set = load_vrp_set(&db, &vrp_cache, date).await?;
+    let mut nets: Vec<IpNet> = fetch_pfx2as(&db, pfx2as_date, Some(&[asn]))
+        .await?
+        .iter()
+        .filter_map(|entry| entry.net())
+        .collect();
+    nets.sort();
+    nets.dedup();
+
+    let (totals, prefixes) = rpki_coverage(&vrp_set, asn, &nets);
+    Ok(Json(RpkiCoverageResponse {
+        asn,
+        date: vrp_set.date.to_string(),
+        totals,
+        prefixes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::vrp_set;
+    use std::str::FromStr;
+
+    fn nets(prefixes: &[&str]) -> Vec<IpNet> {
+        prefixes
+            .iter()
+            .map(|n| IpNet::from_str(n).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rpki_coverage_families() {
+        let set = vrp_set(&[("2001:db8::/32", 48, 64500)]);
+
+        // no announced prefixes, no totals
+        let (totals, prefixes) = rpki_coverage(&set, 64500, &[]);
+        assert!(totals.is_empty());
+        assert!(prefixes.is_empty());
+
+        // families without announced prefixes are left out
+        let (totals, prefixes) = rpki_coverage(&set, 64500, &nets(&["2001:db8:1::/48"]));
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].family, "ipv6");
+        assert_eq!(totals[0].valid_fraction, 1.0);
+        assert_eq!(prefixes[0].covering.len(), 1);
+    }
+
+    #[test]
+    fn test_rpki_coverage_overlapping_prefixes() {
+        let set = vrp_set(&[("10.0.0.0/16", 24, 64500)]);
+        // the valid /24 lies within the valid /16, the other /16 is not covered
+        let (totals, _) = rpki_coverage(
+            &set,
+            64500,
+            &nets(&["10.0.0.0/16", "10.0.1.0/24", "10.1.0.0/16"]),
+        );
+        assert_eq!(totals[0].prefixes, 3);
+        assert_eq!(totals[0].address_space, 512.0);
+        assert_eq!(totals[0].valid_address_space, 256.0);
+        assert_eq!(totals[0].valid_fraction, 0.5);
+    }
+
+    #[test]
+    fn test_rpki_coverage_default_routes() {
+        let set = vrp_set(&[("0.0.0.0/0", 0, 64500), ("::/0", 0, 0)]);
+        let (totals, prefixes) = rpki_coverage(&set, 64500, &nets(&["0.0.0.0/0", "::/0"]));
+        assert_eq!(prefixes[0].state, RovState::Valid);
+        assert_eq!(totals[0].address_space, 16777216.0);
+        assert_eq!(totals[0].valid_fraction, 1.0);
+
+        // the full IPv6 space does not overflow, and AS0 never validates
+        assert_eq!(prefixes[1].reason, Some(RovReason::OriginMismatch));
+        assert_eq!(totals[1].address_space, 2f64.powi(48));
+        assert_eq!(totals[1].valid_fraction, 0.0);
+    }
+}
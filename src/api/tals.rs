@@ -0,0 +1,139 @@
+use crate::api::ApiError;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A trust anchor locator of the RPKI, with the names accepted for it in `tal` filters.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TalInfo {
+    /// canonical name of the TAL, as returned in results
+    name: String,
+
+    /// organization operating the trust anchor
+    organization: String,
+
+    /// alternative names accepted in `tal` filters, case-insensitive and ignoring spaces, `-` and `_`
+    aliases: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TalsResponse {
+    count: usize,
+    data: Vec<TalInfo>,
+}
+
+/// canonical name, organization and aliases of each TAL
+const TAL_CATALOG: &[(&str, &str, &[&str])] = &[
+    (
+        "afrinic",
+        "African Network Information Centre",
+        &["afrinic", "af"],
+    ),
+    (
+        "apnic",
+        "Asia-Pacific Network Information Centre",
+        &["apnic", "ap"],
+    ),
+    ("arin", "American Registry for Internet Numbers", &["arin"]),
+    (
+        "lacnic",
+        "Latin America and Caribbean Network Information Centre",
+        &["lacnic", "lac"],
+    ),
+    (
+        "ripencc",
+        "RIPE Network Coordination Centre",
+        &["ripencc", "ripe", "ncc"],
+    ),
+];
+
+/// canonical names of all TALs
+pub(crate) fn tal_names() -> Vec<&'static str> {
+    TAL_CATALOG.iter().map(|(name, _, _)| *name).collect()
+}
+
+/// Canonical name of a TAL given by name or alias, `None` if unknown.
+pub(crate) fn normalize_tal(tal: &str) -> Option<&'static str> {
+    let normalized: String = tal
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '_')
+        .collect();
+    TAL_CATALOG
+        .iter()
+        .find(|(_, _, aliases)| aliases.contains(&normalized.as_str()))
+        .map(|(name, _, _)| *name)
+}
+
+/// Parse a comma-separated list of TALs into canonical names, rejecting unknown TALs.
+pub(crate) fn parse_tals(tal: &Option<String>) -> Result<Vec<String>, ApiError> {
+    let tal = match tal {
+        None => return Ok(vec![]),
+        Some(t) => t,
+    };
+    let mut tals = vec![];
+    for t in tal.split(',').filter(|t| !t.trim().is_empty()) {
+        match normalize_tal(t) {
+            Some(name) => {
+                if !tals.contains(&name.to_string()) {
+                    tals.push(name.to_string())
+                }
+            }
+            None => {
+                return Err(ApiError::new_bad_request(format!(
+                    "unknown TAL: {}, valid values are {}",
+                    t.trim(),
+                    tal_names().join(", ")
+                )))
+            }
+        }
+    }
+    Ok(tals)
+}
+
+/// List RPKI trust anchor locators.
+///
+/// Returns the canonical name of each TAL along with the aliases accepted by `tal` filters.
+#[utoipa::path(
+    get,
+    tag = "meta",
+    path = "/tals",
+    responses(
+        (status = 200, description = "list of TALs", body = TalsResponse),
+    ),
+)]
+pub async fn list_tals() -> Json<TalsResponse> {
+    let data: Vec<TalInfo> = TAL_CATALOG
+        .iter()
+        .map(|(name, organization, aliases)| TalInfo {
+            name: name.to_string(),
+            organization: organization.to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        })
+        .collect();
+    Json(TalsResponse {
+        count: data.len(),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tals() {
+        assert_eq!(normalize_tal("RIPE NCC"), Some("ripencc"));
+        assert_eq!(normalize_tal("ripe-ncc"), Some("ripencc"));
+        assert_eq!(normalize_tal("LACNIC"), Some("lacnic"));
+        assert_eq!(normalize_tal("radb"), None);
+
+        assert_eq!(
+            parse_tals(&Some("ripe, APNIC,ripencc".to_string())).unwrap(),
+            vec!["ripencc".to_string(), "apnic".to_string()]
+        );
+        assert!(parse_tals(&Some("ripe,radb".to_string())).is_err());
+        assert!(parse_tals(&None).unwrap().is_empty());
+    }
+}
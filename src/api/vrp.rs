@@ -0,0 +1,320 @@
+use crate::api::{query_history_all, ApiError, QueryHistoryParams, RoasRawEntry};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use chrono::NaiveDate;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Validated ROA payload: a single prefix, maximum length and origin ASN authorized by a ROA.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Vrp {
+    pub net: IpNet,
+    pub max_len: u8,
+    pub asn: u32,
+    pub tal: String,
+}
+
+impl Vrp {
+    /// VRP of a raw ROA entry, `None` if the prefix is malformed
+    pub fn from_raw(entry: &RoasRawEntry) -> Option<Self> {
+        let net = IpNet::from_str(entry.prefix.as_str()).ok()?.trunc();
+        Some(Vrp {
+            net,
+            max_len: entry.max_len.min(net.max_prefix_len() as u32) as u8,
+            asn: entry.asn,
+            tal: entry.tal.clone(),
+        })
+    }
+
+    /// whether this VRP covers `prefix`, i.e. `prefix` equals or is more specific than the VRP
+    /// prefix, regardless of max length and origin
+    pub fn covers(&self, prefix: &IpNet) -> bool {
+        self.net.contains(prefix)
+    }
+
+    /// whether this VRP matches a route of `prefix` originated by `origin` per RFC 6811
+    pub fn matches(&self, prefix: &IpNet, origin: u32) -> bool {
+        self.covers(prefix)
+            && prefix.prefix_len() <= self.max_len
+            && self.asn == origin
+            && self.asn != 0
+    }
+}
+
+/// Route origin validation state per RFC 6811.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RovState {
+    Valid,
+    Invalid,
+    NotFound,
+}
+
+/// Reason for a route being RPKI-invalid.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RovReason {
+    /// no covering VRP authorizes the origin AS
+    OriginMismatch,
+
+    /// a covering VRP authorizes the origin AS, but the prefix is longer than its max length
+    MaxLenExceeded,
+}
+
+/// Outcome of validating a single route against a set of VRPs.
+#[derive(Debug)]
+pub struct RovOutcome<'a> {
+    pub state: RovState,
+    pub reason: Option<RovReason>,
+
+    /// VRPs matching the route
+    pub matching: Vec<&'a Vrp>,
+
+    /// all VRPs covering the route prefix, including the matching ones
+    pub covering: Vec<&'a Vrp>,
+}
+
+/// Validate a route of `prefix` originated by `origin` against candidate VRPs per RFC 6811.
+///
+/// Candidates that do not cover the prefix are ignored, so any superset of the covering VRPs can be
+/// passed in.
+pub fn validate_route<'a>(
+    prefix: &IpNet,
+    origin: u32,
+    candidates: impl IntoIterator<Item = &'a Vrp>,
+) -> RovOutcome<'a> {
+    let covering: Vec<&Vrp> = candidates
+        .into_iter()
+        .filter(|vrp| vrp.covers(prefix))
+        .collect();
+    let matching: Vec<&Vrp> = covering
+        .iter()
+        .filter(|vrp| vrp.matches(prefix, origin))
+        .cloned()
+        .collect();
+
+    let (state, reason) = match (covering.is_empty(), matching.is_empty()) {
+        (true, _) => (RovState::NotFound, None),
+        (false, false) => (RovState::Valid, None),
+        (false, true) => match covering.iter().any(|vrp| vrp.asn == origin && vrp.asn != 0) {
+            true => (RovState::Invalid, Some(RovReason::MaxLenExceeded)),
+            false => (RovState::Invalid, Some(RovReason::OriginMismatch)),
+        },
+    };
+
+    RovOutcome {
+        state,
+        reason,
+        matching,
+        covering,
+    }
+}
+
+/// Node of a binary prefix trie, indexed into the arena of [VrpTrie].
+#[derive(Default)]
+struct TrieNode {
+    children: [Option<usize>; 2],
+
+    /// indices of VRPs whose prefix ends at this node
+    vrps: Vec<usize>,
+}
+
+/// Binary prefix trie of VRPs for fast lookup of covering VRPs.
+struct VrpTrie {
+    nodes: Vec<TrieNode>,
+}
+
+impl VrpTrie {
+    fn new() -> Self {
+        VrpTrie {
+            nodes: vec![TrieNode::default()],
+        }
+    }
+
+    fn insert(&mut self, bits: u128, len: u8, vrp_index: usize) {
+        let mut node = 0;
+        for i in 0..len {
+            let bit = ((bits >> (127 - i)) & 1) as usize;
+            node = match self.nodes[node].children[bit] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = Some(child);
+                    child
+                }
+            };
+        }
+        self.nodes[node].vrps.push(vrp_index);
+    }
+
+    /// indices of VRPs whose prefix covers the prefix given by `bits` and `len`
+    fn covering(&self, bits: u128, len: u8) -> Vec<usize> {
+        let mut result = self.nodes[0].vrps.clone();
+        let mut node = 0;
+        for i in 0..len {
+            let bit = ((bits >> (127 - i)) & 1) as usize;
+            match self.nodes[node].children[bit] {
+                Some(child) => node = child,
+                None => break,
+            }
+            result.extend(self.nodes[node].vrps.iter());
+        }
+        result
+    }
+}
+
+/// network address of a prefix left-aligned in 128 bits
+fn prefix_bits(net: &IpNet) -> u128 {
+    match net {
+        IpNet::V4(n) => (u32::from(n.network()) as u128) << 96,
+        IpNet::V6(n) => u128::from(n.network()),
+    }
+}
+
+/// Full set of VRPs valid on a date, indexed by prefix for fast route origin validation.
+pub struct VrpSet {
+    pub date: NaiveDate,
+    pub vrps: Vec<Vrp>,
+    v4: VrpTrie,
+    v6: VrpTrie,
+}
+
+impl VrpSet {
+    pub fn new(date: NaiveDate, vrps: Vec<Vrp>) -> Self {
+        let mut v4 = VrpTrie::new();
+        let mut v6 = VrpTrie::new();
+        for (i, vrp) in vrps.iter().enumerate() {
+            let trie = match vrp.net {
+                IpNet::V4(_) => &mut v4,
+                IpNet::V6(_) => &mut v6,
+            };
+            trie.insert(prefix_bits(&vrp.net), vrp.net.prefix_len(), i);
+        }
+        VrpSet { date, vrps, v4, v6 }
+    }
+
+    /// VRPs covering `prefix`, from the least to the most specific
+    pub fn covering(&self, prefix: &IpNet) -> Vec<&Vrp> {
+        let trie = match prefix {
+            IpNet::V4(_) => &self.v4,
+            IpNet::V6(_) => &self.v6,
+        };
+        trie.covering(prefix_bits(prefix), prefix.prefix_len())
+            .into_iter()
+            .map(|i| &self.vrps[i])
+            .collect()
+    }
+
+    /// validate a route of `prefix` originated by `origin` per RFC 6811
+    pub fn validate(&self, prefix: &IpNet, origin: u32) -> RovOutcome<'_> {
+        validate_route(prefix, origin, self.covering(prefix))
+    }
+}
+
+/// Load all VRPs valid on a date, computing and caching the indexed set on first use.
+pub(crate) async fn load_vrp_set(
+    db: &Arc<BgpkitDatabase>,
+    cache: &TtlCache<VrpSet>,
+    date: NaiveDate,
+) -> Result<Arc<VrpSet>, ApiError> {
+    cache
+        .get_or_try_insert_with(date.to_string().as_str(), || async {
+            let mut params = QueryHistoryParams::new(0, 0);
+            params.date = date.to_string();
+            let entries = query_history_all(db, &params).await?;
+            // indexing the full ROA set takes a while, keep it off the async runtime
+            let built = tokio::task::spawn_blocking(move || {
+                let mut vrps: Vec<Vrp> = entries.iter().filter_map(Vrp::from_raw).collect();
+                vrps.sort();
+                vrps.dedup();
+                VrpSet::new(date, vrps)
+            })
+            .await;
+            match built {
+                Ok(vrp_set) => Ok(vrp_set),
+                Err(_) => Err(ApiError::new_internal("cannot build VRP set")),
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::vrp;
+
+    #[test]
+    fn test_validate_route() {
+        let vrps = vec![
+            vrp("1.1.1.0/24", 24, 13335),
+            vrp("1.0.0.0/8", 16, 64500),
+            vrp("2001:db8::/32", 48, 64501),
+        ];
+        let net = |s: &str| IpNet::from_str(s).unwrap();
+
+        let outcome = validate_route(&net("1.1.1.0/24"), 13335, &vrps);
+        assert_eq!(outcome.state, RovState::Valid);
+        assert_eq!(outcome.matching.len(), 1);
+        assert_eq!(outcome.covering.len(), 2);
+
+        let outcome = validate_route(&net("1.1.1.0/24"), 64501, &vrps);
+        assert_eq!(outcome.state, RovState::Invalid);
+        assert_eq!(outcome.reason, Some(RovReason::OriginMismatch));
+
+        let outcome = validate_route(&net("1.2.3.0/24"), 64500, &vrps);
+        assert_eq!(outcome.state, RovState::Invalid);
+        assert_eq!(outcome.reason, Some(RovReason::MaxLenExceeded));
+
+        let outcome = validate_route(&net("2001:db8:1::/48"), 64501, &vrps);
+        assert_eq!(outcome.state, RovState::Valid);
+
+        let outcome = validate_route(&net("8.8.8.0/24"), 15169, &vrps);
+        assert_eq!(outcome.state, RovState::NotFound);
+        assert!(outcome.covering.is_empty());
+    }
+
+    #[test]
+    fn test_vrp_set() {
+        let set = VrpSet::new(
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            vec![
+                vrp("0.0.0.0/0", 8, 64496),
+                vrp("1.0.0.0/8", 16, 64500),
+                vrp("1.1.1.0/24", 24, 13335),
+                vrp("1.1.2.0/24", 24, 13335),
+                vrp("2001:db8::/32", 48, 64501),
+            ],
+        );
+        let net = |s: &str| IpNet::from_str(s).unwrap();
+
+        let covering: Vec<u32> = set
+            .covering(&net("1.1.1.0/24"))
+            .iter()
+            .map(|v| v.asn)
+            .collect();
+        assert_eq!(covering, vec![64496, 64500, 13335]);
+        assert_eq!(set.covering(&net("2001:db8::/48")).len(), 1);
+        assert!(set.covering(&net("2001:db9::/48")).is_empty());
+
+        assert_eq!(
+            set.validate(&net("1.1.1.0/24"), 13335).state,
+            RovState::Valid
+        );
+        assert_eq!(
+            set.validate(&net("1.1.3.0/24"), 64500).state,
+            RovState::Invalid
+        );
+        assert_eq!(
+            set.validate(&net("1.2.0.0/16"), 64500).state,
+            RovState::Valid
+        );
+        assert_eq!(
+            set.validate(&net("2001:db9::/32"), 64501).state,
+            RovState::NotFound
+        );
+    }
+}
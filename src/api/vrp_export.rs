@@ -0,0 +1,196 @@
+use crate::api::{load_vrp_set, parse_tals, parse_vrp_date, ApiError, Vrp, VrpSet};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use axum::body::StreamBody;
+use axum::extract::Query;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use futures::stream;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+/// number of VRPs rendered per streamed chunk
+const EXPORT_CHUNK_SIZE: usize = 1000;
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct VrpExportQuery {
+    /// date of the VRP set, format: YYYY-MM-DD. defaults to the previous day UTC
+    date: Option<String>,
+
+    /// filter results by trust anchor, e.g. `ripencc` or `ripe`. use comma to separate multiple TALs,
+    /// see `/tals` for valid values and aliases
+    tal: Option<String>,
+
+    /// export format: `rpki-client` (default, rpki-client JSON), `csv` (Routinator CSV) or
+    /// `openbgpd` (OpenBGPD `roa-set`)
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VrpExportFormat {
+    RpkiClient,
+    RoutinatorCsv,
+    OpenBgpd,
+}
+
+impl VrpExportFormat {
+    fn parse(format: &Option<String>) -> Result<Self, ApiError> {
+        match format.as_deref().map(|f| f.to_lowercase()) {
+            None => Ok(VrpExportFormat::RpkiClient),
+            Some(f) => match f.as_str() {
+                "rpki-client" | "json" => Ok(VrpExportFormat::RpkiClient),
+                "csv" | "routinator" => Ok(VrpExportFormat::RoutinatorCsv),
+                "openbgpd" | "bgpd" => Ok(VrpExportFormat::OpenBgpd),
+                _ => Err(ApiError::new_bad_request(format!(
+                    "unknown export format: {}, valid values are `rpki-client`, `csv` and `openbgpd`",
+                    f
+                ))),
+            },
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            VrpExportFormat::RpkiClient => "application/json",
+            VrpExportFormat::RoutinatorCsv => "text/csv",
+            VrpExportFormat::OpenBgpd => "text/plain",
+        }
+    }
+
+    fn header(&self, set: &VrpSet, count: usize) -> String {
+        match self {
+            VrpExportFormat::RpkiClient => format!(
+                "{{\"metadata\":{{\"date\":\"{}\",\"vrps\":{}}},\"roas\":[",
+                set.date, count
+            ),
+            VrpExportFormat::RoutinatorCsv => "ASN,IP Prefix,Max Length,Trust Anchor\n".to_string(),
+            VrpExportFormat::OpenBgpd => "roa-set {\n".to_string(),
+        }
+    }
+
+    fn footer(&self) -> String {
+        match self {
+            VrpExportFormat::RpkiClient => "\n]}\n".to_string(),
+            VrpExportFormat::RoutinatorCsv => "".to_string(),
+            VrpExportFormat::OpenBgpd => "}\n".to_string(),
+        }
+    }
+
+    /// render a single VRP, `first` marks the first VRP of the export
+    fn line(&self, vrp: &Vrp, first: bool) -> String {
+        match self {
+            VrpExportFormat::RpkiClient => format!(
+                "{}\n{{\"asn\":{},\"prefix\":\"{}\",\"maxLength\":{},\"ta\":\"{}\"}}",
+                if first { "" } else { "," },
+                vrp.asn,
+                vrp.net,
+                vrp.max_len,
+                vrp.tal
+            ),
+            VrpExportFormat::RoutinatorCsv => {
+                format!("AS{},{},{},{}\n", vrp.asn, vrp.net, vrp.max_len, vrp.tal)
+            }
+            VrpExportFormat::OpenBgpd => format!(
+                "\t{} maxlen {} source-as {}\n",
+                vrp.net, vrp.max_len, vrp.asn
+            ),
+        }
+    }
+}
+
+/// Export the full set of VRPs valid on a date.
+///
+/// The VRPs are streamed in rpki-client JSON, Routinator CSV or OpenBGPD `roa-set` format, and can
+/// be loaded directly into relying party software or routers to replay a historical state.
+#[utoipa::path(
+    get,
+    tag = "bgp",
+    path = "/vrps",
+    responses(
+        (status = 200, description = "VRP set in the requested format", body = String),
+        (status = 400, description = "invalid query parameters"),
+    ),
+    params(
+        VrpExportQuery,
+    )
+)]
+pub async fn export_vrps(
+    Extension(db): Extension<Arc<BgpkitDatabase>>,
+    Extension(vrp_cache): Extension<Arc<TtlCache<VrpSet>>>,
+    query: Query<VrpExportQuery>,
+) -> Result<Response, ApiError> {
+    let format = VrpExportFormat::parse(&query.format)?;
+    let tals = parse_tals(&query.tal)?;
+    let set = load_vrp_set(&db, &vrp_cache, parse_vrp_date(&query.date)?).await?;
+
+    // indices of the exported VRPs
+    let indices: Arc<Vec<usize>> = Arc::new(
+        set.vrps
+            .iter()
+            .enumerate()
+            .filter(|(_, vrp)| tals.is_empty() || tals.contains(&vrp.tal.to_lowercase()))
+            .map(|(i, _)| i)
+            .collect(),
+    );
+
+    let head = format.header(&set, indices.len());
+    let footer = format.footer();
+    let num_chunks = indices.len().div_ceil(EXPORT_CHUNK_SIZE);
+    let chunks = (0..num_chunks).map(move |chunk| {
+        let start = chunk * EXPORT_CHUNK_SIZE;
+        let end = (start + EXPORT_CHUNK_SIZE).min(indices.len());
+        (start..end)
+            .map(|i| format.line(&set.vrps[indices[i]], i == 0))
+            .collect::<String>()
+    });
+    let body = std::iter::once(head)
+        .chain(chunks)
+        .chain(std::iter::once(footer))
+        .map(Ok::<String, Infallible>);
+
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type())],
+        StreamBody::new(stream::iter(body)),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use ipnet::IpNet;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_export_formats() {
+        let vrp = Vrp {
+            net: IpNet::from_str("1.1.1.0/24").unwrap(),
+            max_len: 24,
+            asn: 13335,
+            tal: "apnic".to_string(),
+        };
+        let set = VrpSet::new(
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            vec![vrp.clone()],
+        );
+
+        let format = VrpExportFormat::RpkiClient;
+        let json = format.header(&set, 1) + &format.line(&vrp, true) + &format.footer();
+        let value: serde_json::Value = serde_json::from_str(json.as_str()).unwrap();
+        assert_eq!(value["roas"][0]["maxLength"], 24);
+        assert_eq!(value["roas"][0]["ta"], "apnic");
+
+        assert_eq!(
+            VrpExportFormat::RoutinatorCsv.line(&vrp, true),
+            "AS13335,1.1.1.0/24,24,apnic\n"
+        );
+        assert_eq!(
+            VrpExportFormat::OpenBgpd.line(&vrp, true),
+            "\t1.1.1.0/24 maxlen 24 source-as 13335\n"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::api::ApiError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// default maximum number of entries kept by a cache
+const DEFAULT_MAX_ENTRIES: usize = 64;
+
+struct CacheEntry<V> {
+    /// last time the entry was requested, for least-recently-used eviction
+    last_used: Instant,
+
+    /// the value along with the time it was computed, empty while the value is being computed
+    cell: Arc<OnceCell<(Instant, Arc<V>)>>,
+}
+
+/// Simple in-memory cache for expensive derived datasets, keyed by string with a fixed time-to-live.
+///
+/// Expired entries are dropped on access, and the least recently used entry is evicted once the
+/// cache holds its maximum number of entries.
+pub struct TtlCache<V> {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, CacheEntry<V>>>,
+}
+
+impl<V> TtlCache<V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_capacity(ttl, DEFAULT_MAX_ENTRIES)
+    }
+
+    /// Create a cache holding at most `max_entries` values.
+    pub fn with_capacity(ttl: Duration, max_entries: usize) -> Self {
+        TtlCache {
+            ttl,
+            max_entries: max_entries.max(1),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cell holding the value of `key`, creating it if missing or expired.
+    fn cell(&self, key: &str) -> Arc<OnceCell<(Instant, Arc<V>)>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let ttl = self.ttl;
+        entries.retain(|_, entry| {
+            entry
+                .cell
+                .get()
+                .is_none_or(|(computed, _)| computed.elapsed() < ttl)
+        });
+        if !entries.contains_key(key) && entries.len() >= self.max_entries {
+            let lru = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru) = lru {
+                entries.remove(&lru);
+            }
+        }
+
+        let now = Instant::now();
+        let entry = entries
+            .entry(key.to_string())
+            .or_insert_with(|| CacheEntry {
+                last_used: now,
+                cell: Arc::new(OnceCell::new()),
+            });
+        entry.last_used = now;
+        entry.cell.clone()
+    }
+
+    /// Return the cached value for `key` if it has not expired, or compute and cache it with `f`.
+    ///
+    /// Errors from `f` are returned as-is and not cached. Concurrent callers of the same key wait
+    /// for the ongoing computation instead of repeating it, while other keys are not blocked.
+    pub async fn get_or_try_insert_with<F, Fut>(&self, key: &str, f: F) -> Result<Arc<V>, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, ApiError>>,
+    {
+        let cell = self.cell(key);
+        let (_, value) = cell
+            .get_or_try_init(|| async {
+                let value = f().await?;
+                Ok::<_, ApiError>((Instant::now(), Arc::new(value)))
+            })
+            .await?;
+        Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ttl_cache() {
+        let cache: TtlCache<u32> = TtlCache::new(Duration::from_secs(60));
+        let v = cache.get_or_try_insert_with("a", || async { Ok(1) }).await;
+        assert_eq!(*v.unwrap(), 1);
+        let v = cache.get_or_try_insert_with("a", || async { Ok(2) }).await;
+        assert_eq!(*v.unwrap(), 1);
+
+        let v = cache
+            .get_or_try_insert_with("b", || async { Err(ApiError::new_internal("failed")) })
+            .await;
+        assert!(v.is_err());
+        let v = cache.get_or_try_insert_with("b", || async { Ok(3) }).await;
+        assert_eq!(*v.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_cache_eviction() {
+        // least recently used entry is evicted at capacity
+        let cache: TtlCache<u32> = TtlCache::with_capacity(Duration::from_secs(60), 2);
+        for (key, value) in [("a", 1), ("b", 2), ("a", 0), ("c", 3)] {
+            let _ = cache
+                .get_or_try_insert_with(key, || async move { Ok(value) })
+                .await;
+        }
+        let v = cache.get_or_try_insert_with("a", || async { Ok(0) }).await;
+        assert_eq!(*v.unwrap(), 1);
+        let v = cache.get_or_try_insert_with("b", || async { Ok(0) }).await;
+        assert_eq!(*v.unwrap(), 0);
+
+        // expired entries are recomputed
+        let cache: TtlCache<u32> = TtlCache::new(Duration::ZERO);
+        let _ = cache.get_or_try_insert_with("a", || async { Ok(1) }).await;
+        let v = cache.get_or_try_insert_with("a", || async { Ok(2) }).await;
+        assert_eq!(*v.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_cache_keys_do_not_block() {
+        let cache: Arc<TtlCache<u32>> = Arc::new(TtlCache::new(Duration::from_secs(60)));
+        let (tx, rx) = tokio::sync::oneshot::channel::<u32>();
+        let slow = {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_try_insert_with("slow", || async { Ok(rx.await.unwrap()) })
+                    .await
+            })
+        };
+        tokio::task::yield_now().await;
+
+        // another key completes while `slow` is still being computed
+        let v = cache
+            .get_or_try_insert_with("fast", || async { Ok(1) })
+            .await;
+        assert_eq!(*v.unwrap(), 1);
+        tx.send(2).unwrap();
+        assert_eq!(*slow.await.unwrap().unwrap(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::api::ApiError;
+use crate::db::BgpkitDatabase;
+use axum::http::StatusCode;
+use postgrest::Builder;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Database objects beyond the baseline schema that some endpoints rely on.
+///
+/// Their definitions are in `sql/extensions.sql`. Each one is probed at startup, and endpoints
+/// relying on a missing one answer `501 Not Implemented` instead of failing upstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DbFeature {
+    /// `query_history` parameters beyond the baseline ones: `nics`, `asns`, `match`, `date_from`,
+    /// `date_to`, `date_range_match`, `family`, `prefix_min_len` and `prefix_max_len`
+    RoaHistoryFilters,
+
+    /// `query_aspa_history` function
+    AspaHistory,
+
+    /// `items_latest` view with the most recent file of each collector and data type
+    ItemsLatest,
+
+    /// `pfx2as` table and `pfx2as_latest` view of prefix-to-origin mappings
+    Pfx2As,
+}
+
+impl DbFeature {
+    pub const ALL: [DbFeature; 4] = [
+        DbFeature::RoaHistoryFilters,
+        DbFeature::AspaHistory,
+        DbFeature::ItemsLatest,
+        DbFeature::Pfx2As,
+    ];
+
+    /// name of the database objects, as used in error messages
+    pub fn objects(&self) -> &'static str {
+        match self {
+            DbFeature::RoaHistoryFilters => "extended `query_history` function",
+            DbFeature::AspaHistory => "`query_aspa_history` function",
+            DbFeature::ItemsLatest => "`items_latest` view",
+            DbFeature::Pfx2As => "`pfx2as` table and `pfx2as_latest` view",
+        }
+    }
+
+    /// minimal requests that fail if the objects are missing
+    fn probes(&self, db: &BgpkitDatabase) -> Vec<Builder> {
+        match self {
+            DbFeature::RoaHistoryFilters => {
+                let params = json!({
+                    "res_limit": 1, "res_offset": 0, "prefix": "", "asn": -1, "max_len": -1,
+                    "nic": "", "date": "", "not_date": "", "nics": ["apnic"], "asns": [0],
+                    "match": "exact", "date_from": "2023-01-01", "date_to": "2023-01-01",
+                    "date_range_match": "any", "family": 4, "prefix_min_len": 0,
+                    "prefix_max_len": 32,
+                });
+                vec![db.client.rpc("query_history", params.to_string())]
+            }
+            DbFeature::AspaHistory => {
+                let params = json!({
+                    "res_limit": 1, "res_offset": 0, "customer_asn": -1, "provider_asn": -1,
+                    "nic": "", "date": "", "nics": ["apnic"],
+                });
+                vec![db.client.rpc("query_aspa_history", params.to_string())]
+            }
+            DbFeature::ItemsLatest => {
+                vec![db
+                    .client
+                    .from("items_latest")
+                    .select("collector_id")
+                    .limit(1)]
+            }
+            DbFeature::Pfx2As => vec![
+                db.client.from("pfx2as").select("prefix").limit(1),
+                db.client.from("pfx2as_latest").select("prefix").limit(1),
+            ],
+        }
+    }
+}
+
+/// optional features found at startup, `None` until probed
+static AVAILABLE: OnceLock<HashSet<DbFeature>> = OnceLock::new();
+
+/// Probe which optional database objects are installed.
+///
+/// Only a client error response, e.g. an unknown function, table or parameter, marks a feature as
+/// missing. A feature whose probe cannot reach the database is assumed to be installed.
+pub async fn probe_features(db: &BgpkitDatabase) {
+    let mut available = HashSet::new();
+    for feature in DbFeature::ALL {
+        let mut installed = true;
+        for probe in feature.probes(db) {
+            if let Ok(response) = probe.execute().await {
+                installed &= !response.status().is_client_error();
+            }
+        }
+        match installed {
+            true => {
+                info!("optional database feature available: {}", feature.objects());
+                available.insert(feature);
+            }
+            false => warn!(
+                "optional database feature missing, dependent endpoints are disabled: {}",
+                feature.objects()
+            ),
+        }
+    }
+    let _ = AVAILABLE.set(available);
+}
+
+/// Fail with `501 Not Implemented` if an optional database feature was found missing at startup.
+pub fn require_feature(feature: DbFeature) -> Result<(), ApiError> {
+    match AVAILABLE.get() {
+        Some(available) if !available.contains(&feature) => Err(ApiError::new(
+            StatusCode::NOT_IMPLEMENTED.as_u16(),
+            format!(
+                "not supported by this deployment: the database lacks the {}",
+                feature.objects()
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
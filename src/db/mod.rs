@@ -1,6 +1,12 @@
+mod features;
+
+pub use features::*;
+
 use crate::api::ApiError;
 use postgrest::Builder;
 use postgrest::Postgrest;
+use serde::de::DeserializeOwned;
+use std::future::Future;
 
 pub struct BgpkitDatabase {
     pub client: Postgrest,
@@ -18,6 +24,12 @@ impl BgpkitDatabase {
     }
 }
 
+impl Default for BgpkitDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub async fn execute(builder: Builder) -> Result<String, ApiError> {
     let response = match builder.execute().await {
         Ok(r) => r,
@@ -34,11 +46,140 @@ pub async fn execute(builder: Builder) -> Result<String, ApiError> {
     Ok(text)
 }
 
+/// Total number of rows from a PostgREST `Content-Range` header, e.g. `0-99/1234` or `*/0`.
+fn content_range_total(content_range: &str) -> Option<usize> {
+    content_range.split('/').nth(1)?.trim().parse().ok()
+}
+
+/// Execute a query and return the response body along with the total number of matching rows,
+/// as counted by the database with `Prefer: count=exact`.
+pub async fn execute_with_count(builder: Builder) -> Result<(String, Option<usize>), ApiError> {
+    let response = match builder.exact_count().execute().await {
+        Ok(r) => r,
+        Err(_) => return Err(ApiError::new_internal("database request failed")),
+    };
+    let total = response
+        .headers()
+        .get("content-range")
+        .and_then(|v| v.to_str().ok())
+        .and_then(content_range_total);
+    let text = match response.text().await {
+        Ok(t) => t,
+        Err(_) => {
+            return Err(ApiError::new_internal(
+                "extracting text from response failed",
+            ))
+        }
+    };
+    Ok((text, total))
+}
+
+/// Execute a query page by page and collect all rows, for queries whose result may exceed the
+/// maximum number of rows returned by a single request.
+///
+/// `order` must be a total order of the result, e.g. ending with the primary key columns, so that
+/// pages neither overlap nor skip rows. The end of the result is detected from the row count
+/// reported by the database for the first page, so a server-side limit on rows per request below
+/// `page_size` does not end the iteration early.
+pub async fn execute_all<T: DeserializeOwned>(
+    builder: Builder,
+    order: &str,
+    page_size: usize,
+) -> Result<Vec<T>, ApiError> {
+    let builder = builder.order(order);
+    let mut rows = vec![];
+    let mut total = None;
+    loop {
+        let low = rows.len();
+        let page_builder = builder.clone().range(low, low + page_size - 1);
+        // only count the rows once, counting is a full scan of the result
+        let text = match low {
+            0 => {
+                let (text, count) = execute_with_count(page_builder).await?;
+                total = count;
+                text
+            }
+            _ => execute(page_builder).await?,
+        };
+        let page: Vec<T> = match serde_json::from_str(text.as_str()) {
+            Ok(p) => p,
+            Err(_) => return Err(ApiError::new_internal("cannot parse database response")),
+        };
+        if page.is_empty() {
+            break;
+        }
+        rows.extend(page);
+        // without a row count, keep going until an empty page
+        if total.is_some_and(|total| rows.len() >= total) {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+/// Call `fetch(offset, limit)` page by page and collect all rows, for database functions paged by
+/// offset and limit that cannot report a row count.
+///
+/// The offset advances by the number of rows returned and the iteration ends on the first empty
+/// page, so a server-side limit on rows per request below `page_size` does not truncate the result.
+/// Stops early once more than `max_rows` rows are collected.
+pub async fn fetch_pages<T, F, Fut>(
+    page_size: usize,
+    max_rows: Option<usize>,
+    mut fetch: F,
+) -> Result<Vec<T>, ApiError>
+where
+    F: FnMut(usize, usize) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, ApiError>>,
+{
+    let mut rows = vec![];
+    loop {
+        let page = fetch(rows.len(), page_size).await?;
+        if page.is_empty() {
+            break;
+        }
+        rows.extend(page);
+        if max_rows.is_some_and(|max| rows.len() > max) {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::api::AsnInfo;
 
+    #[test]
+    fn test_content_range_total() {
+        assert_eq!(content_range_total("0-99/1234"), Some(1234));
+        assert_eq!(content_range_total("*/0"), Some(0));
+        assert_eq!(content_range_total("0-99/*"), None);
+        assert_eq!(content_range_total(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_pages() {
+        // the server returns at most 3 rows per request, fewer than the requested page size
+        let source: Vec<u32> = (0..10).collect();
+        let fetch = |offset: usize, limit: usize| {
+            let page: Vec<u32> = source
+                .iter()
+                .skip(offset)
+                .take(limit.min(3))
+                .cloned()
+                .collect();
+            async move { Ok(page) }
+        };
+        assert_eq!(fetch_pages(5, None, fetch).await.unwrap(), source);
+        assert_eq!(fetch_pages(5, Some(4), fetch).await.unwrap().len(), 6);
+        assert!(fetch_pages(5, None, |_, _| async { Ok(Vec::<u32>::new()) })
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_connection() {
         let db = BgpkitDatabase::new();
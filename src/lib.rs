@@ -1,8 +1,19 @@
-use crate::api::{search_asninfo, search_broker, search_roas, search_peer_stats};
+use crate::api::{
+    bulk_as_relationships, bulk_asninfo, bulk_validate_rov, diff_roas_between, export_vrps,
+    list_bogon_asns, list_collectors, list_tals, ranked_search_asninfo, search_as_relationships,
+    search_asninfo, search_aspas, search_bogon_asn, search_broker, search_broker_latest,
+    search_broker_snapshot, search_cone, search_cone_ranking, search_expired_roas, search_new_roas,
+    search_peer_stats, search_roa_stats, search_roa_timeline, search_roas, search_rov_invalids,
+    search_rpki_coverage, validate_rov, verify_aspa, AspaSet, BrokerLatestSet, CollectorsSummary,
+    ConeIndex, RoaChurnEntries, RoaStatsSet, RovInvalidSet, VrpSet,
+};
+use crate::cache::TtlCache;
 use crate::db::BgpkitDatabase;
+use axum::extract::DefaultBodyLimit;
 use axum::http::{Method, StatusCode};
 use axum::{routing, Extension, Router};
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::info;
 
@@ -11,10 +22,12 @@ use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 
 pub mod api;
+pub mod cache;
 pub mod db;
+pub mod rtr;
 
 async fn health_check() -> StatusCode {
-    return StatusCode::OK;
+    StatusCode::OK
 }
 
 pub async fn start_service() {
@@ -22,14 +35,58 @@ pub async fn start_service() {
     #[openapi(
         paths(
             api::search_asninfo,
+            api::bulk_asninfo,
+            api::ranked_search_asninfo,
+            api::search_as_relationships,
+            api::bulk_as_relationships,
+            api::search_cone,
+            api::search_cone_ranking,
+            api::search_rpki_coverage,
+            api::search_bogon_asn,
+            api::list_bogon_asns,
             api::search_roas,
+            api::diff_roas_between,
+            api::search_roa_timeline,
+            api::search_roa_stats,
+            api::search_expired_roas,
+            api::search_new_roas,
+            api::validate_rov,
+            api::bulk_validate_rov,
+            api::search_rov_invalids,
+            api::export_vrps,
+            api::list_tals,
+            api::search_aspas,
+            api::verify_aspa,
             api::search_broker,
+            api::search_broker_latest,
+            api::search_broker_snapshot,
+            api::list_collectors,
             api::search_peer_stats,
         ),
     components(
-        schemas(api::AsnInfo, api::AsninfoResponse),
+        schemas(api::AsnInfo, api::AsninfoResponse, api::AsninfoBulkResponse),
+        schemas(api::AsnInfoMatch, api::AsninfoRankedResponse),
+        schemas(api::AsRelationship, api::AsNeighbor),
+        schemas(api::AsRelationshipsResponse, api::AsRelationshipsBulkResponse),
+        schemas(api::ConeSize, api::ConeResponse, api::ConeRankingResponse),
+        schemas(api::SpecialPurposeAsn, api::BogonAsnResponse, api::BogonAsnListResponse),
         schemas(api::BrokerEntry, api::BrokerResponse),
+        schemas(api::BrokerLatestEntry, api::BrokerLatestResponse),
+        schemas(api::BrokerSnapshot, api::BrokerSnapshotResponse),
+        schemas(api::CollectorInfo, api::CollectorsResponse),
         schemas(api::RoasEntry, api::RoasResponse),
+        schemas(api::RoasDiffEntry, api::RoasDiffCounts, api::RoasDiffResponse),
+        schemas(api::RoaEvent, api::RoaTimelineResponse),
+        schemas(api::RoaStats, api::RoaStatsResponse),
+        schemas(api::RoaChange, api::RoaChurnResponse),
+        schemas(api::RovState, api::RovReason, api::RovResponse),
+        schemas(api::TalInfo, api::TalsResponse),
+        schemas(api::AspaEntry, api::AspasResponse, api::AspaHopResult, api::AspaState),
+        schemas(api::AspaHop, api::AspaVerifyRequest, api::AspaVerifyResponse),
+        schemas(api::RpkiCoveringVrp, api::RpkiPrefixCoverage, api::RpkiCoverageTotals),
+        schemas(api::RpkiCoverageResponse),
+        schemas(api::RovBulkResult, api::RovCounts, api::RovBulkResponse),
+        schemas(api::RovInvalid, api::RovInvalidsResponse),
         schemas(api::PeerStats, api::PeerStatsResponse)
     ),
     modifiers( &Intro ),
@@ -66,15 +123,85 @@ pub async fn start_service() {
         // allow requests from any origin
         .allow_origin(Any);
 
+    dotenvy::dotenv().ok();
+    // optional custom or private collector projects and collectors, as a JSON file
+    if let Ok(path) = std::env::var("BGPKIT_COLLECTORS_FILE") {
+        api::load_custom_registry(path.as_str())
+            .expect("cannot load BGPKIT_COLLECTORS_FILE collector registry");
+    }
+
     let db = Arc::new(BgpkitDatabase::new());
+    // endpoints relying on database objects beyond the baseline schema are disabled if missing
+    db::probe_features(&db).await;
+    let cone_cache: Arc<TtlCache<ConeIndex>> =
+        Arc::new(TtlCache::new(Duration::from_secs(6 * 3600)));
+    // VRP sets and invalid announcement sets are large, only keep a few dates in memory
+    let vrp_cache: Arc<TtlCache<VrpSet>> =
+        Arc::new(TtlCache::with_capacity(Duration::from_secs(3600), 4));
+    let invalid_cache: Arc<TtlCache<RovInvalidSet>> =
+        Arc::new(TtlCache::with_capacity(Duration::from_secs(3600), 4));
+    let aspa_cache: Arc<TtlCache<AspaSet>> =
+        Arc::new(TtlCache::with_capacity(Duration::from_secs(3600), 4));
+    // ROA statistics are small, keep those of recent queries
+    let roa_stats_cache: Arc<TtlCache<RoaStatsSet>> =
+        Arc::new(TtlCache::with_capacity(Duration::from_secs(6 * 3600), 64));
+    let roa_churn_cache: Arc<TtlCache<RoaChurnEntries>> =
+        Arc::new(TtlCache::with_capacity(Duration::from_secs(3600), 16));
+    let broker_latest_cache: Arc<TtlCache<BrokerLatestSet>> =
+        Arc::new(TtlCache::new(Duration::from_secs(5 * 60)));
+    // keyed by the set of collectors with files, which rarely changes
+    let collectors_cache: Arc<TtlCache<CollectorsSummary>> =
+        Arc::new(TtlCache::with_capacity(Duration::from_secs(3600), 4));
+    let (rtr_db, rtr_vrp_cache) = (db.clone(), vrp_cache.clone());
     let app = Router::new()
         .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
         .route("/asninfo", routing::get(search_asninfo))
+        .route("/asninfo/bulk", routing::post(bulk_asninfo))
+        .route("/asninfo/search", routing::get(ranked_search_asninfo))
+        .route("/asninfo/cones", routing::get(search_cone_ranking))
+        .route("/asninfo/:asn/cone", routing::get(search_cone))
+        .route("/asninfo/:asn/rpki", routing::get(search_rpki_coverage))
+        .route(
+            "/as-relationships/bulk",
+            routing::post(bulk_as_relationships),
+        )
+        .route(
+            "/as-relationships/:asn",
+            routing::get(search_as_relationships),
+        )
+        .route("/bogons/asn", routing::get(list_bogon_asns))
+        .route("/bogons/asn/:asn", routing::get(search_bogon_asn))
         .route("/roas", routing::get(search_roas))
+        .route("/roas/diff", routing::get(diff_roas_between))
+        .route("/roas/timeline", routing::get(search_roa_timeline))
+        .route("/roas/stats", routing::get(search_roa_stats))
+        .route("/roas/expired", routing::get(search_expired_roas))
+        .route("/roas/new", routing::get(search_new_roas))
+        .route("/vrps", routing::get(export_vrps))
+        .route("/tals", routing::get(list_tals))
+        .route("/aspas", routing::get(search_aspas))
+        .route("/aspa/verify", routing::post(verify_aspa))
+        .route("/rov", routing::get(validate_rov))
+        .route(
+            "/rov/bulk",
+            routing::post(bulk_validate_rov).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+        )
+        .route("/rov/invalids", routing::get(search_rov_invalids))
         .route("/broker", routing::get(search_broker))
+        .route("/broker/latest", routing::get(search_broker_latest))
+        .route("/broker/snapshot", routing::get(search_broker_snapshot))
+        .route("/collectors", routing::get(list_collectors))
         .route("/peers", routing::get(search_peer_stats))
         .route("/health_check", routing::get(health_check))
         .layer(Extension(db))
+        .layer(Extension(cone_cache))
+        .layer(Extension(vrp_cache))
+        .layer(Extension(invalid_cache))
+        .layer(Extension(roa_stats_cache))
+        .layer(Extension(aspa_cache))
+        .layer(Extension(roa_churn_cache))
+        .layer(Extension(broker_latest_cache))
+        .layer(Extension(collectors_cache))
         .layer(cors);
 
     dotenvy::dotenv().ok();
@@ -82,6 +209,31 @@ pub async fn start_service() {
     let addr_str = format!("0.0.0.0:{}", port_str);
     let addr = addr_str.parse::<std::net::SocketAddr>().unwrap();
 
+    // optional RTR server, serving the VRPs of `BGPKIT_RTR_DATE` (YYYY-MM-DD or `latest`)
+    if let Ok(rtr_port) = std::env::var("BGPKIT_RTR_PORT") {
+        let rtr_addr = format!("0.0.0.0:{}", rtr_port)
+            .parse::<std::net::SocketAddr>()
+            .unwrap();
+        let rtr_date = match std::env::var("BGPKIT_RTR_DATE") {
+            Ok(d) if d != "latest" => Some(
+                chrono::NaiveDate::parse_from_str(d.as_str(), "%Y-%m-%d")
+                    .expect("BGPKIT_RTR_DATE must be YYYY-MM-DD or latest"),
+            ),
+            _ => None,
+        };
+        let refresh = std::env::var("BGPKIT_RTR_REFRESH")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(3600);
+        tokio::spawn(rtr::start_rtr_service(
+            rtr_db,
+            rtr_vrp_cache,
+            rtr_addr,
+            rtr_date,
+            Duration::from_secs(refresh),
+        ));
+    }
+
     info!("start listening to address http://{}", addr.to_string());
     info!("docs available at http://{}/docs", addr.to_string());
     axum::Server::bind(&addr)
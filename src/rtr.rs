@@ -0,0 +1,566 @@
+//! RPKI-to-Router (RTR) protocol server.
+//!
+//! Implements the cache side of RTR version 1 (RFC 8210) and version 0 (RFC 6810), serving VRPs
+//! from an [RtrCache]. Routers connecting to the server receive the full VRP set on a Reset Query,
+//! incremental updates on a Serial Query, and a Serial Notify whenever the cache is updated.
+
+use crate::api::{latest_roa_date, load_vrp_set, VrpSet};
+use crate::cache::TtlCache;
+use crate::db::BgpkitDatabase;
+use chrono::{NaiveDate, Utc};
+use ipnet::IpNet;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, RwLock};
+use tracing::{info, warn};
+
+const PDU_SERIAL_NOTIFY: u8 = 0;
+const PDU_SERIAL_QUERY: u8 = 1;
+const PDU_RESET_QUERY: u8 = 2;
+const PDU_CACHE_RESPONSE: u8 = 3;
+const PDU_IPV4_PREFIX: u8 = 4;
+const PDU_IPV6_PREFIX: u8 = 6;
+const PDU_END_OF_DATA: u8 = 7;
+const PDU_CACHE_RESET: u8 = 8;
+const PDU_ERROR_REPORT: u8 = 10;
+
+const ERR_CORRUPT_DATA: u16 = 0;
+const ERR_NO_DATA_AVAILABLE: u16 = 2;
+const ERR_INVALID_REQUEST: u16 = 3;
+const ERR_UNSUPPORTED_VERSION: u16 = 4;
+const ERR_UNSUPPORTED_PDU_TYPE: u16 = 5;
+const ERR_UNEXPECTED_VERSION: u16 = 8;
+
+/// highest supported protocol version
+const MAX_VERSION: u8 = 1;
+
+/// largest PDU accepted from routers, large enough for any error report with a reasonable text
+const MAX_PDU_LEN: u32 = 64 * 1024;
+
+/// number of incremental updates kept to answer Serial Queries
+const MAX_DELTAS: usize = 16;
+
+/// timing parameters sent in version 1 End of Data PDUs, in seconds
+const REFRESH_INTERVAL: u32 = 3600;
+const RETRY_INTERVAL: u32 = 600;
+const EXPIRE_INTERVAL: u32 = 7200;
+
+/// A VRP as served over RTR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RtrVrp {
+    pub prefix: IpNet,
+    pub max_len: u8,
+    pub asn: u32,
+}
+
+/// VRPs announced and withdrawn when moving to `serial`.
+struct Delta {
+    serial: u32,
+    announced: Vec<RtrVrp>,
+    withdrawn: Vec<RtrVrp>,
+}
+
+struct RtrSnapshot {
+    serial: u32,
+    vrps: Arc<BTreeSet<RtrVrp>>,
+    deltas: VecDeque<Delta>,
+}
+
+/// VRP data served by the RTR server, with serial numbers and recent incremental updates.
+pub struct RtrCache {
+    session_id: u16,
+    snapshot: RwLock<Option<RtrSnapshot>>,
+    notify: watch::Sender<u32>,
+}
+
+/// Response to a Serial Query.
+enum SerialResponse {
+    /// announced and withdrawn VRPs since the router's serial, up to the current serial
+    Delta(u32, Vec<RtrVrp>, Vec<RtrVrp>),
+
+    /// the router's serial is unknown, it has to start over with a Reset Query
+    Reset,
+}
+
+impl RtrCache {
+    pub fn new(session_id: u16) -> Self {
+        let (notify, _) = watch::channel(0);
+        RtrCache {
+            session_id,
+            snapshot: RwLock::new(None),
+            notify,
+        }
+    }
+
+    /// Replace the served VRP set.
+    ///
+    /// If the set changed, the serial number is incremented and connected routers are notified.
+    /// Returns the current serial number.
+    pub async fn update(&self, vrps: impl IntoIterator<Item = RtrVrp>) -> u32 {
+        let vrps: BTreeSet<RtrVrp> = vrps.into_iter().collect();
+        let mut guard = self.snapshot.write().await;
+        let snapshot = match guard.as_mut() {
+            None => {
+                *guard = Some(RtrSnapshot {
+                    serial: 0,
+                    vrps: Arc::new(vrps),
+                    deltas: VecDeque::new(),
+                });
+                let _ = self.notify.send(0);
+                return 0;
+            }
+            Some(s) => s,
+        };
+
+        let announced: Vec<RtrVrp> = vrps.difference(&snapshot.vrps).cloned().collect();
+        let withdrawn: Vec<RtrVrp> = snapshot.vrps.difference(&vrps).cloned().collect();
+        if announced.is_empty() && withdrawn.is_empty() {
+            return snapshot.serial;
+        }
+
+        snapshot.serial = snapshot.serial.wrapping_add(1);
+        snapshot.vrps = Arc::new(vrps);
+        snapshot.deltas.push_back(Delta {
+            serial: snapshot.serial,
+            announced,
+            withdrawn,
+        });
+        if snapshot.deltas.len() > MAX_DELTAS {
+            snapshot.deltas.pop_front();
+        }
+        let serial = snapshot.serial;
+        let _ = self.notify.send(serial);
+        serial
+    }
+
+    /// current serial number and full VRP set, `None` if no data has been loaded yet
+    async fn full(&self) -> Option<(u32, Arc<BTreeSet<RtrVrp>>)> {
+        self.snapshot
+            .read()
+            .await
+            .as_ref()
+            .map(|s| (s.serial, s.vrps.clone()))
+    }
+
+    /// changes since `serial`, `None` if no data has been loaded yet
+    async fn since(&self, serial: u32) -> Option<SerialResponse> {
+        let guard = self.snapshot.read().await;
+        let snapshot = guard.as_ref()?;
+        if serial == snapshot.serial {
+            return Some(SerialResponse::Delta(serial, vec![], vec![]));
+        }
+        let start = match snapshot
+            .deltas
+            .iter()
+            .position(|d| d.serial == serial.wrapping_add(1))
+        {
+            Some(pos) => pos,
+            None => return Some(SerialResponse::Reset),
+        };
+
+        // net change of each VRP over all deltas: true for announced, false for withdrawn
+        let mut changes: BTreeMap<RtrVrp, bool> = BTreeMap::new();
+        for delta in snapshot.deltas.iter().skip(start) {
+            for vrp in &delta.announced {
+                if changes.remove(vrp) != Some(false) {
+                    changes.insert(*vrp, true);
+                }
+            }
+            for vrp in &delta.withdrawn {
+                if changes.remove(vrp) != Some(true) {
+                    changes.insert(*vrp, false);
+                }
+            }
+        }
+        let announced = changes
+            .iter()
+            .filter(|(_, a)| **a)
+            .map(|(v, _)| *v)
+            .collect();
+        let withdrawn = changes
+            .iter()
+            .filter(|(_, a)| !**a)
+            .map(|(v, _)| *v)
+            .collect();
+        Some(SerialResponse::Delta(snapshot.serial, announced, withdrawn))
+    }
+}
+
+fn pdu_header(version: u8, pdu_type: u8, session: u16, len: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(len as usize);
+    buf.push(version);
+    buf.push(pdu_type);
+    buf.extend_from_slice(&session.to_be_bytes());
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf
+}
+
+fn serial_notify_pdu(version: u8, session: u16, serial: u32) -> Vec<u8> {
+    let mut buf = pdu_header(version, PDU_SERIAL_NOTIFY, session, 12);
+    buf.extend_from_slice(&serial.to_be_bytes());
+    buf
+}
+
+fn prefix_pdu(version: u8, vrp: &RtrVrp, announce: bool) -> Vec<u8> {
+    let flags = u8::from(announce);
+    let mut buf = match vrp.prefix {
+        IpNet::V4(net) => {
+            let mut buf = pdu_header(version, PDU_IPV4_PREFIX, 0, 20);
+            buf.extend_from_slice(&[flags, net.prefix_len(), vrp.max_len, 0]);
+            buf.extend_from_slice(&net.network().octets());
+            buf
+        }
+        IpNet::V6(net) => {
+            let mut buf = pdu_header(version, PDU_IPV6_PREFIX, 0, 32);
+            buf.extend_from_slice(&[flags, net.prefix_len(), vrp.max_len, 0]);
+            buf.extend_from_slice(&net.network().octets());
+            buf
+        }
+    };
+    buf.extend_from_slice(&vrp.asn.to_be_bytes());
+    buf
+}
+
+fn end_of_data_pdu(version: u8, session: u16, serial: u32) -> Vec<u8> {
+    let len = match version {
+        0 => 12,
+        _ => 24,
+    };
+    let mut buf = pdu_header(version, PDU_END_OF_DATA, session, len);
+    buf.extend_from_slice(&serial.to_be_bytes());
+    if version > 0 {
+        buf.extend_from_slice(&REFRESH_INTERVAL.to_be_bytes());
+        buf.extend_from_slice(&RETRY_INTERVAL.to_be_bytes());
+        buf.extend_from_slice(&EXPIRE_INTERVAL.to_be_bytes());
+    }
+    buf
+}
+
+fn error_report_pdu(version: u8, code: u16, pdu: &[u8], text: &str) -> Vec<u8> {
+    let len = 16 + pdu.len() + text.len();
+    let mut buf = pdu_header(version, PDU_ERROR_REPORT, code, len as u32);
+    buf.extend_from_slice(&(pdu.len() as u32).to_be_bytes());
+    buf.extend_from_slice(pdu);
+    buf.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    buf.extend_from_slice(text.as_bytes());
+    buf
+}
+
+/// Send the VRP changes since the router's serial, or a Cache Reset if they are not available.
+///
+/// Returns whether a response was sent, i.e. not a No Data Available error.
+async fn send_serial_response(
+    stream: &mut OwnedWriteHalf,
+    cache: &RtrCache,
+    version: u8,
+    serial: u32,
+) -> io::Result<bool> {
+    let (current, announced, withdrawn) = match cache.since(serial).await {
+        None => {
+            let pdu = error_report_pdu(version, ERR_NO_DATA_AVAILABLE, &[], "no data available");
+            stream.write_all(&pdu).await?;
+            return Ok(false);
+        }
+        Some(SerialResponse::Reset) => {
+            stream
+                .write_all(&pdu_header(version, PDU_CACHE_RESET, 0, 8))
+                .await?;
+            return Ok(true);
+        }
+        Some(SerialResponse::Delta(current, announced, withdrawn)) => {
+            (current, announced, withdrawn)
+        }
+    };
+
+    let mut buf = pdu_header(version, PDU_CACHE_RESPONSE, cache.session_id, 8);
+    for vrp in &withdrawn {
+        buf.extend(prefix_pdu(version, vrp, false));
+    }
+    for vrp in &announced {
+        buf.extend(prefix_pdu(version, vrp, true));
+    }
+    buf.extend(end_of_data_pdu(version, cache.session_id, current));
+    stream.write_all(&buf).await?;
+    Ok(true)
+}
+
+/// Send the full VRP set.
+///
+/// Returns whether a response was sent, i.e. not a No Data Available error.
+async fn send_reset_response(
+    stream: &mut OwnedWriteHalf,
+    cache: &RtrCache,
+    version: u8,
+) -> io::Result<bool> {
+    let (serial, vrps) = match cache.full().await {
+        None => {
+            let pdu = error_report_pdu(version, ERR_NO_DATA_AVAILABLE, &[], "no data available");
+            stream.write_all(&pdu).await?;
+            return Ok(false);
+        }
+        Some(data) => data,
+    };
+
+    let mut buf = pdu_header(version, PDU_CACHE_RESPONSE, cache.session_id, 8);
+    for vrp in vrps.iter() {
+        buf.extend(prefix_pdu(version, vrp, true));
+        if buf.len() > 64 * 1024 {
+            stream.write_all(&buf).await?;
+            buf.clear();
+        }
+    }
+    buf.extend(end_of_data_pdu(version, cache.session_id, serial));
+    stream.write_all(&buf).await?;
+    Ok(true)
+}
+
+/// Handle a single PDU from a router. Returns whether the connection should be kept open.
+///
+/// The protocol version is negotiated once the cache answers a query with a Cache Response or
+/// Cache Reset. Until then, queries of any supported version are accepted, e.g. from a router
+/// falling back to version 0 after an error.
+async fn handle_pdu(
+    stream: &mut OwnedWriteHalf,
+    cache: &RtrCache,
+    version: &mut Option<u8>,
+    pdu: &[u8],
+) -> io::Result<bool> {
+    let pdu_version = pdu[0];
+    let pdu_type = pdu[1];
+
+    if pdu_version > MAX_VERSION {
+        let err = error_report_pdu(
+            MAX_VERSION,
+            ERR_UNSUPPORTED_VERSION,
+            pdu,
+            "unsupported protocol version",
+        );
+        stream.write_all(&err).await?;
+        return Ok(false);
+    }
+    match version {
+        Some(v) if *v != pdu_version => {
+            let err = error_report_pdu(
+                *v,
+                ERR_UNEXPECTED_VERSION,
+                pdu,
+                "unexpected protocol version",
+            );
+            stream.write_all(&err).await?;
+            return Ok(false);
+        }
+        _ => {}
+    }
+
+    match pdu_type {
+        PDU_SERIAL_QUERY if pdu.len() == 12 => {
+            let session = u16::from_be_bytes([pdu[2], pdu[3]]);
+            let serial = u32::from_be_bytes([pdu[8], pdu[9], pdu[10], pdu[11]]);
+            if session != cache.session_id {
+                stream
+                    .write_all(&pdu_header(pdu_version, PDU_CACHE_RESET, 0, 8))
+                    .await?;
+                *version = Some(pdu_version);
+                return Ok(true);
+            }
+            if send_serial_response(stream, cache, pdu_version, serial).await? {
+                *version = Some(pdu_version);
+            }
+            Ok(true)
+        }
+        PDU_RESET_QUERY if pdu.len() == 8 => {
+            if send_reset_response(stream, cache, pdu_version).await? {
+                *version = Some(pdu_version);
+            }
+            Ok(true)
+        }
+        PDU_SERIAL_QUERY | PDU_RESET_QUERY => {
+            let err = error_report_pdu(pdu_version, ERR_CORRUPT_DATA, pdu, "invalid PDU length");
+            stream.write_all(&err).await?;
+            Ok(false)
+        }
+        PDU_ERROR_REPORT => {
+            warn!("RTR error report received from router, closing connection");
+            Ok(false)
+        }
+        PDU_SERIAL_NOTIFY | PDU_CACHE_RESPONSE | PDU_IPV4_PREFIX | PDU_IPV6_PREFIX
+        | PDU_END_OF_DATA | PDU_CACHE_RESET => {
+            let err = error_report_pdu(
+                pdu_version,
+                ERR_INVALID_REQUEST,
+                pdu,
+                "unexpected PDU from router",
+            );
+            stream.write_all(&err).await?;
+            Ok(false)
+        }
+        _ => {
+            let err = error_report_pdu(
+                pdu_version,
+                ERR_UNSUPPORTED_PDU_TYPE,
+                pdu,
+                "unsupported PDU type",
+            );
+            stream.write_all(&err).await?;
+            Ok(false)
+        }
+    }
+}
+
+/// PDU read from a router
+enum RouterPdu {
+    /// complete PDU, header included
+    Pdu(Vec<u8>),
+
+    /// header of a PDU with a length out of range, after which the stream cannot be resynchronized
+    InvalidLength([u8; 8]),
+}
+
+/// Read PDUs from a router and forward them until the router disconnects.
+///
+/// Reading in a dedicated task keeps partially read PDUs intact while the connection handler
+/// waits on other events.
+async fn read_pdus(mut reader: OwnedReadHalf, tx: mpsc::Sender<RouterPdu>) {
+    loop {
+        let mut header = [0u8; 8];
+        if reader.read_exact(&mut header).await.is_err() {
+            return;
+        }
+        let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        if !(8..=MAX_PDU_LEN).contains(&len) {
+            let _ = tx.send(RouterPdu::InvalidLength(header)).await;
+            return;
+        }
+        let mut pdu = header.to_vec();
+        pdu.resize(len as usize, 0);
+        if reader.read_exact(&mut pdu[8..]).await.is_err() {
+            return;
+        }
+        if tx.send(RouterPdu::Pdu(pdu)).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, cache: Arc<RtrCache>) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let (tx, mut pdus) = mpsc::channel(8);
+    let reader_task = tokio::spawn(read_pdus(reader, tx));
+    let result = serve_router(&mut writer, &cache, &mut pdus).await;
+    reader_task.abort();
+    result
+}
+
+async fn serve_router(
+    stream: &mut OwnedWriteHalf,
+    cache: &RtrCache,
+    pdus: &mut mpsc::Receiver<RouterPdu>,
+) -> io::Result<()> {
+    let mut notify = cache.notify.subscribe();
+    notify.borrow_and_update();
+    let mut version: Option<u8> = None;
+    // version of the last PDU from the router, to notify it before a version is negotiated
+    let mut router_version: Option<u8> = None;
+
+    loop {
+        tokio::select! {
+            pdu = pdus.recv() => match pdu {
+                // connection closed by router
+                None => return Ok(()),
+                Some(RouterPdu::InvalidLength(header)) => {
+                    let err = error_report_pdu(
+                        version.unwrap_or(header[0]).min(MAX_VERSION),
+                        ERR_CORRUPT_DATA,
+                        &header,
+                        "invalid PDU length",
+                    );
+                    stream.write_all(&err).await?;
+                    return Ok(());
+                }
+                Some(RouterPdu::Pdu(pdu)) => {
+                    if pdu[0] <= MAX_VERSION {
+                        router_version = Some(pdu[0]);
+                    }
+                    if !handle_pdu(stream, cache, &mut version, &pdu).await? {
+                        return Ok(());
+                    }
+                }
+            },
+            res = notify.changed() => {
+                if res.is_err() {
+                    return Ok(());
+                }
+                let serial = *notify.borrow_and_update();
+                // routers are only notified once they have sent a PDU in a supported version
+                if let Some(v) = version.or(router_version) {
+                    stream.write_all(&serial_notify_pdu(v, cache.session_id, serial)).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Accept RTR connections on `listener` and serve VRPs from `cache`.
+pub async fn serve(listener: TcpListener, cache: Arc<RtrCache>) -> io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        info!("RTR connection from {}", addr);
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, cache).await {
+                warn!("RTR connection from {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Start the RTR server on `addr`, serving the VRPs of `date`, or of the previous day UTC with
+/// periodic refreshes if no date is given.
+pub(crate) async fn start_rtr_service(
+    db: Arc<BgpkitDatabase>,
+    vrp_cache: Arc<TtlCache<VrpSet>>,
+    addr: SocketAddr,
+    date: Option<NaiveDate>,
+    refresh: std::time::Duration,
+) {
+    let cache = Arc::new(RtrCache::new(Utc::now().timestamp() as u16));
+
+    let updater_cache = cache.clone();
+    tokio::spawn(async move {
+        loop {
+            let vrp_date = date.unwrap_or_else(latest_roa_date);
+            match load_vrp_set(&db, &vrp_cache, vrp_date).await {
+                Ok(set) => {
+                    let vrps = set.vrps.iter().map(|vrp| RtrVrp {
+                        prefix: vrp.net,
+                        max_len: vrp.max_len,
+                        asn: vrp.asn,
+                    });
+                    let serial = updater_cache.update(vrps).await;
+                    info!("RTR serving VRPs of {} with serial {}", vrp_date, serial);
+                    if date.is_some() {
+                        // historical VRP sets never change
+                        return;
+                    }
+                }
+                Err(e) => warn!("loading VRPs for RTR failed: {}", e),
+            }
+            tokio::time::sleep(refresh).await;
+        }
+    });
+
+    match TcpListener::bind(addr).await {
+        Ok(listener) => {
+            info!("RTR server listening on {}", addr);
+            if let Err(e) = serve(listener, cache).await {
+                warn!("RTR server stopped: {}", e);
+            }
+        }
+        Err(e) => warn!("cannot bind RTR server to {}: {}", addr, e),
+    }
+}
@@ -0,0 +1,268 @@
+use bgpkit_api_rs::rtr::{serve, RtrCache, RtrVrp};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// PDU received by the test client
+#[derive(Debug, PartialEq)]
+enum Pdu {
+    SerialNotify { session: u16, serial: u32 },
+    CacheResponse { session: u16 },
+    Prefix { announce: bool, vrp: RtrVrp },
+    EndOfData { session: u16, serial: u32 },
+    CacheReset,
+    ErrorReport { code: u16 },
+}
+
+struct RtrClient {
+    stream: TcpStream,
+    version: u8,
+}
+
+impl RtrClient {
+    async fn connect(addr: std::net::SocketAddr, version: u8) -> Self {
+        RtrClient {
+            stream: TcpStream::connect(addr).await.unwrap(),
+            version,
+        }
+    }
+
+    async fn reset_query(&mut self) {
+        let pdu = [self.version, 2, 0, 0, 0, 0, 0, 8];
+        self.stream.write_all(&pdu).await.unwrap();
+    }
+
+    async fn serial_query(&mut self, session: u16, serial: u32) {
+        let mut pdu = vec![self.version, 1];
+        pdu.extend_from_slice(&session.to_be_bytes());
+        pdu.extend_from_slice(&12u32.to_be_bytes());
+        pdu.extend_from_slice(&serial.to_be_bytes());
+        self.stream.write_all(&pdu).await.unwrap();
+    }
+
+    async fn read_pdu(&mut self) -> Pdu {
+        let mut header = [0u8; 8];
+        tokio::time::timeout(Duration::from_secs(5), self.stream.read_exact(&mut header))
+            .await
+            .expect("timed out waiting for PDU")
+            .unwrap();
+        let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let mut body = vec![0u8; len - 8];
+        self.stream.read_exact(&mut body).await.unwrap();
+        assert_eq!(header[0], self.version);
+
+        let session = u16::from_be_bytes([header[2], header[3]]);
+        let u32_at =
+            |i: usize| u32::from_be_bytes([body[i], body[i + 1], body[i + 2], body[i + 3]]);
+        match header[1] {
+            0 => Pdu::SerialNotify {
+                session,
+                serial: u32_at(0),
+            },
+            3 => Pdu::CacheResponse { session },
+            4 | 6 => {
+                let addr = match header[1] {
+                    4 => IpAddr::from(<[u8; 4]>::try_from(&body[4..8]).unwrap()),
+                    _ => IpAddr::from(<[u8; 16]>::try_from(&body[4..20]).unwrap()),
+                };
+                Pdu::Prefix {
+                    announce: body[0] == 1,
+                    vrp: RtrVrp {
+                        prefix: ipnet::IpNet::new(addr, body[1]).unwrap(),
+                        max_len: body[2],
+                        asn: u32_at(body.len() - 4),
+                    },
+                }
+            }
+            7 => {
+                let expected_len = match self.version {
+                    0 => 12,
+                    _ => 24,
+                };
+                assert_eq!(len, expected_len);
+                Pdu::EndOfData {
+                    session,
+                    serial: u32_at(0),
+                }
+            }
+            8 => Pdu::CacheReset,
+            10 => Pdu::ErrorReport { code: session },
+            t => panic!("unexpected PDU type {}", t),
+        }
+    }
+
+    /// read a full cache response, returning the prefix PDUs and the serial number
+    async fn read_response(&mut self, session: u16) -> (Vec<(bool, RtrVrp)>, u32) {
+        assert_eq!(self.read_pdu().await, Pdu::CacheResponse { session });
+        let mut prefixes = vec![];
+        loop {
+            match self.read_pdu().await {
+                Pdu::Prefix { announce, vrp } => prefixes.push((announce, vrp)),
+                Pdu::EndOfData { session: s, serial } => {
+                    assert_eq!(s, session);
+                    return (prefixes, serial);
+                }
+                pdu => panic!("unexpected PDU {:?}", pdu),
+            }
+        }
+    }
+}
+
+fn vrp(prefix: &str, max_len: u8, asn: u32) -> RtrVrp {
+    RtrVrp {
+        prefix: ipnet::IpNet::from_str(prefix).unwrap(),
+        max_len,
+        asn,
+    }
+}
+
+async fn start_server(cache: Arc<RtrCache>) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener, cache));
+    addr
+}
+
+#[tokio::test]
+async fn test_rtr_reset_and_serial_query() {
+    let session = 4242;
+    let cache = Arc::new(RtrCache::new(session));
+    let addr = start_server(cache.clone()).await;
+    let mut client = RtrClient::connect(addr, 1).await;
+
+    // no data loaded yet
+    client.reset_query().await;
+    assert_eq!(client.read_pdu().await, Pdu::ErrorReport { code: 2 });
+
+    cache
+        .update(vec![
+            vrp("1.1.1.0/24", 24, 13335),
+            vrp("2001:db8::/32", 48, 64500),
+        ])
+        .await;
+    assert_eq!(
+        client.read_pdu().await,
+        Pdu::SerialNotify { session, serial: 0 }
+    );
+    client.reset_query().await;
+    let (prefixes, serial) = client.read_response(session).await;
+    assert_eq!(serial, 0);
+    assert_eq!(
+        prefixes,
+        vec![
+            (true, vrp("1.1.1.0/24", 24, 13335)),
+            (true, vrp("2001:db8::/32", 48, 64500)),
+        ]
+    );
+
+    // refresh: one VRP withdrawn, one announced
+    cache
+        .update(vec![
+            vrp("1.1.1.0/24", 24, 13335),
+            vrp("8.8.8.0/24", 24, 15169),
+        ])
+        .await;
+    assert_eq!(
+        client.read_pdu().await,
+        Pdu::SerialNotify { session, serial: 1 }
+    );
+    client.serial_query(session, serial).await;
+    let (prefixes, serial) = client.read_response(session).await;
+    assert_eq!(serial, 1);
+    assert_eq!(
+        prefixes,
+        vec![
+            (false, vrp("2001:db8::/32", 48, 64500)),
+            (true, vrp("8.8.8.0/24", 24, 15169)),
+        ]
+    );
+
+    // up to date
+    client.serial_query(session, serial).await;
+    let (prefixes, _) = client.read_response(session).await;
+    assert!(prefixes.is_empty());
+
+    // unknown serial or session
+    client.serial_query(session, 100).await;
+    assert_eq!(client.read_pdu().await, Pdu::CacheReset);
+    client.serial_query(session + 1, serial).await;
+    assert_eq!(client.read_pdu().await, Pdu::CacheReset);
+}
+
+#[tokio::test]
+async fn test_rtr_version_negotiation() {
+    let session = 7;
+    let cache = Arc::new(RtrCache::new(session));
+    cache.update(vec![vrp("1.1.1.0/24", 24, 13335)]).await;
+    let addr = start_server(cache.clone()).await;
+
+    // version 0 routers get version 0 PDUs
+    let mut client = RtrClient::connect(addr, 0).await;
+    client.reset_query().await;
+    let (prefixes, _) = client.read_response(session).await;
+    assert_eq!(prefixes.len(), 1);
+
+    // unsupported versions are rejected
+    let mut client = RtrClient::connect(addr, 2).await;
+    client.reset_query().await;
+    client.version = 1;
+    assert_eq!(client.read_pdu().await, Pdu::ErrorReport { code: 4 });
+}
+
+#[tokio::test]
+async fn test_rtr_version_fallback_before_negotiation() {
+    let session = 9;
+    let cache = Arc::new(RtrCache::new(session));
+    let addr = start_server(cache.clone()).await;
+    let mut client = RtrClient::connect(addr, 1).await;
+
+    // no version is negotiated by an error, so the router may still fall back to version 0
+    client.reset_query().await;
+    assert_eq!(client.read_pdu().await, Pdu::ErrorReport { code: 2 });
+    cache.update(vec![vrp("1.1.1.0/24", 24, 13335)]).await;
+    assert_eq!(
+        client.read_pdu().await,
+        Pdu::SerialNotify { session, serial: 0 }
+    );
+    client.version = 0;
+    client.reset_query().await;
+    let (prefixes, serial) = client.read_response(session).await;
+    assert_eq!(prefixes.len(), 1);
+
+    // the version is fixed once the cache answered
+    client.version = 1;
+    client.serial_query(session, serial).await;
+    client.version = 0;
+    assert_eq!(client.read_pdu().await, Pdu::ErrorReport { code: 8 });
+}
+
+#[tokio::test]
+async fn test_rtr_partial_pdu_across_notify() {
+    let session = 11;
+    let cache = Arc::new(RtrCache::new(session));
+    cache.update(vec![vrp("1.1.1.0/24", 24, 13335)]).await;
+    let addr = start_server(cache.clone()).await;
+    let mut client = RtrClient::connect(addr, 1).await;
+    client.reset_query().await;
+    let (_, serial) = client.read_response(session).await;
+
+    // a notification sent while a query is partially received does not corrupt the query
+    let mut pdu = vec![1, 1];
+    pdu.extend_from_slice(&session.to_be_bytes());
+    pdu.extend_from_slice(&12u32.to_be_bytes());
+    pdu.extend_from_slice(&serial.to_be_bytes());
+    client.stream.write_all(&pdu[..6]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    cache.update(vec![vrp("8.8.8.0/24", 24, 15169)]).await;
+    assert_eq!(
+        client.read_pdu().await,
+        Pdu::SerialNotify { session, serial: 1 }
+    );
+    client.stream.write_all(&pdu[6..]).await.unwrap();
+    let (prefixes, serial) = client.read_response(session).await;
+    assert_eq!(serial, 1);
+    assert_eq!(prefixes.len(), 2);
+}